@@ -2,14 +2,26 @@ use egui::{
     Key, Modifiers,
     epaint::text::{FontInsert, InsertFontFamily},
 };
-use std::{collections::HashSet, sync::mpsc};
-
-use crate::models::audio_file::{
-    AlbumArtError, AudioCodec, AudioContainer, AudioFile, AudioSampleRate,
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, Mutex, mpsc},
 };
-use crate::models::settings::{AppTheme, OutputGrouping, Settings};
+
+use crate::cover_art_cache::CoverArtCache;
+use crate::models::audio_file::{self, AlbumArtError, AudioFile, AudioFileError};
+use crate::models::file_filter::FileFilter;
+use crate::models::settings::{AppTheme, FileColumn, FileView, Settings, SortKey, SourceAction};
+use crate::models::task;
+use crate::playlist::Playlist;
+use crate::scan_cache::ScanCache;
+use crate::scanner::{ScanOptions, Scanner};
 use crate::tasks_manager::TasksManager;
 use crate::ui;
+use crate::ui::album_view::AlbumThumbnail;
+use crate::ui::sync::SyncState;
+use crate::verify::SourceVerifier;
 
 pub const NO_ARTIST: &str = "<no artist>";
 pub const NO_ALBUM: &str = "<no album>";
@@ -17,8 +29,10 @@ pub const NO_TITLE: &str = "<no title>";
 
 pub struct AppState {
     pub files: Vec<AudioFile>,
+    pub cover_art_cache: Arc<CoverArtCache>,
     pub cover_art_rx: Option<mpsc::Receiver<Result<egui::ColorImage, AlbumArtError>>>,
     pub cover_art: Option<egui::TextureHandle>,
+    pub album_thumbnails: HashMap<PathBuf, AlbumThumbnail>,
 
     pub lg_cover_art_rx: Option<mpsc::Receiver<Result<egui::ColorImage, AlbumArtError>>>,
     pub lg_cover_art: Option<egui::TextureHandle>,
@@ -26,11 +40,193 @@ pub struct AppState {
 
     pub is_transcoding: bool,
 
+    pub scanners: Vec<Scanner>,
+    pub scan_cache: Arc<Mutex<ScanCache>>,
+    pub import_errors: Vec<(PathBuf, AudioFileError)>,
+    pub showing_import_report: bool,
+    /// Written into the output directory along with the files they list
+    pub playlists: Vec<Playlist>,
+
+    pub filter: FileFilter,
+    pub visible_rows: Vec<usize>,
+    /// The filter `visible_rows` was computed with, and whether files were added or removed since
+    visible_filter: FileFilter,
+    visible_rows_dirty: bool,
+
+    /// Settings being edited to convert just the selected files with
+    pub selection_settings: Option<Settings>,
+    /// Files waiting for the user to confirm their sources can be moved or deleted
+    pub pending_conversion: Option<(Vec<AudioFile>, Settings)>,
+    /// Why the last conversion wasn't queued, e.g. targets writing the same files
+    pub conversion_error: Option<String>,
+
+    pub preset_name: String,
+    pub preset_error: Option<String>,
+
+    pub sync: SyncState,
+    pub showing_sync: bool,
+
+    pub source_verifier: Option<SourceVerifier>,
+
     pub table_selections: HashSet<usize>,
     pub first_selection: Option<usize>,
     pub last_selection: Option<usize>,
 }
 
+impl AppState {
+    /// Re-applies the filter, deselecting rows it hides so actions only ever affect visible rows
+    pub fn update_visible_rows(&mut self) {
+        self.visible_rows = self.filter.visible_rows(&self.files);
+        self.visible_filter = self.filter.clone();
+        self.visible_rows_dirty = false;
+
+        let visible = &self.visible_rows;
+        self.table_selections
+            .retain(|i| visible.binary_search(i).is_ok());
+
+        if self.table_selections.is_empty() {
+            self.first_selection = None;
+            self.last_selection = None;
+        } else if self
+            .last_selection
+            .is_none_or(|i| !self.table_selections.contains(&i))
+        {
+            self.last_selection = self.table_selections.iter().min().copied();
+        }
+    }
+
+    /// Re-applies the filter only if it or the files changed since, filtering every file each
+    /// frame is slow with large libraries
+    pub fn refresh_visible_rows(&mut self) {
+        if self.visible_rows_dirty || self.filter != self.visible_filter {
+            self.update_visible_rows();
+        }
+    }
+
+    /// Selected files in table order
+    pub fn selected_files(&self) -> Vec<AudioFile> {
+        let mut selected: Vec<usize> = self.table_selections.iter().copied().collect();
+        selected.sort();
+
+        selected
+            .into_iter()
+            .map(|i| self.files[i].clone())
+            .collect()
+    }
+
+    /// Queues the files, or asks first when their sources would be moved or deleted afterwards.
+    /// Nothing is queued when targets would overwrite each other's outputs
+    pub fn convert(
+        &mut self,
+        files: Vec<AudioFile>,
+        settings: &Settings,
+        tasks_manager: &mut TasksManager,
+    ) {
+        let collisions = task::colliding_outputs(&files, settings);
+        if let Some((path, targets)) = collisions.first() {
+            let others = match collisions.len() {
+                1 => String::new(),
+                n => format!(" and {} other file(s)", n - 1),
+            };
+            self.conversion_error = Some(format!(
+                "The targets {} would all write {}{others}\nGive them different output directories or containers",
+                targets.join(", "),
+                path.display()
+            ));
+            return;
+        }
+
+        let handles_sources = settings
+            .target_settings()
+            .iter()
+            .any(|(_, s)| s.out_source_action != SourceAction::Keep);
+        if handles_sources {
+            self.pending_conversion = Some((files, settings.clone()));
+            return;
+        }
+
+        self.queue_conversion(files, settings, tasks_manager);
+    }
+
+    /// Queues the files, along with the playlists that list any of them
+    pub fn queue_conversion(
+        &mut self,
+        files: Vec<AudioFile>,
+        settings: &Settings,
+        tasks_manager: &mut TasksManager,
+    ) {
+        let paths: HashSet<&PathBuf> = files.iter().map(|f| &f.path).collect();
+        for playlist in &self.playlists {
+            if playlist
+                .entries
+                .iter()
+                .any(|e| e.path.as_ref().is_some_and(|p| paths.contains(p)))
+            {
+                tasks_manager.queue_playlist(playlist.clone(), &self.files, settings, None);
+            }
+        }
+
+        for file in files {
+            tasks_manager.queue_audio_file(file, settings);
+        }
+        self.is_transcoding = true;
+    }
+
+    pub fn remove_selected(&mut self) {
+        if self.table_selections.is_empty() {
+            return;
+        }
+
+        self.files = self
+            .files
+            .clone()
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !self.table_selections.contains(i))
+            .map(|(_, f)| f)
+            .collect();
+
+        self.table_selections.clear();
+        self.first_selection = None;
+        self.last_selection = None;
+        self.update_visible_rows();
+    }
+
+    fn select_only(&mut self, i: usize) {
+        self.table_selections.clear();
+        self.table_selections.insert(i);
+        self.first_selection = Some(i);
+        self.last_selection = Some(i);
+        self.cover_art_rx = Some(self.files[i].load_album_art(Some(300), &self.cover_art_cache));
+    }
+}
+
+#[derive(Clone, Copy)]
+enum RowAction {
+    Convert,
+    ConvertWithSettings,
+    Remove,
+    ShowInfo,
+    OpenFolder,
+}
+
+/// Opens the file manager, there's no portable way to also highlight the file
+fn open_containing_folder(path: &std::path::Path) {
+    let Some(folder) = path.parent() else {
+        return;
+    };
+
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+
+    let _ = std::process::Command::new(program).arg(folder).spawn();
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 pub struct AudioConverterApp {
@@ -44,34 +240,43 @@ pub struct AudioConverterApp {
 
 impl Default for AudioConverterApp {
     fn default() -> Self {
+        let cover_art_cache = Arc::new(CoverArtCache::new());
+
         Self {
             app_state: AppState {
                 files: Vec::new(),
+                cover_art_cache: cover_art_cache.clone(),
                 cover_art_rx: None,
                 cover_art: None,
+                album_thumbnails: HashMap::new(),
                 lg_cover_art_rx: None,
                 lg_cover_art: None,
                 showing_lg_art: false,
                 is_transcoding: false,
+                scanners: Vec::new(),
+                scan_cache: Arc::new(Mutex::new(ScanCache::default())),
+                import_errors: Vec::new(),
+                showing_import_report: false,
+                playlists: Vec::new(),
+                filter: FileFilter::default(),
+                visible_rows: Vec::new(),
+                visible_filter: FileFilter::default(),
+                visible_rows_dirty: false,
+                selection_settings: None,
+                pending_conversion: None,
+                conversion_error: None,
+                preset_name: String::new(),
+                preset_error: None,
+                sync: SyncState::Idle,
+                showing_sync: false,
+                source_verifier: None,
                 table_selections: HashSet::new(),
                 first_selection: None,
                 last_selection: None,
             },
-            tasks_manager: TasksManager::new(),
-
-            settings: Settings {
-                app_theme: AppTheme::System,
-                run_concurrent_task_count: 2,
-                out_codec: AudioCodec::OPUS,
-                out_container: AudioContainer::OGG,
-                out_sample_rate: AudioSampleRate::Studio48,
-                out_bitrate: 64000,
-                out_directory: "./".to_string(),
-                out_grouping: OutputGrouping::ArtistAlbum,
-                out_embed_art: true,
-                out_enable_cover_art_resize: false,
-                out_cover_art_resolution: 1000,
-            },
+            tasks_manager: TasksManager::new(cover_art_cache),
+
+            settings: Settings::default(),
         }
     }
 }
@@ -109,11 +314,25 @@ impl AudioConverterApp {
             }],
         ));
 
-        if let Some(storage) = cc.storage {
+        let mut app: Self = if let Some(storage) = cc.storage {
             eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
         } else {
             Default::default()
-        }
+        };
+        app.app_state.scan_cache = Arc::new(Mutex::new(ScanCache::load()));
+
+        app
+    }
+
+    /// The settings last saved by the window, or the defaults, for running without one
+    pub fn stored_settings() -> Settings {
+        let stored = eframe::storage_dir(crate::APP_NAME)
+            .and_then(|dir| std::fs::read_to_string(dir.join("app.ron")).ok())
+            .and_then(|ron| ron::from_str::<HashMap<String, String>>(&ron).ok())
+            .and_then(|storage| storage.get(eframe::APP_KEY).cloned())
+            .and_then(|app| ron::from_str::<Self>(&app).ok());
+
+        stored.unwrap_or_default().settings
     }
 
     fn preview_dropped_files(&mut self, ctx: &egui::Context) {
@@ -155,6 +374,164 @@ impl AudioConverterApp {
         }
     }
 
+    fn start_scan(&mut self, paths: Vec<PathBuf>) {
+        if self.app_state.scanners.is_empty() {
+            self.app_state.import_errors.clear();
+        }
+        self.app_state.scanners.push(Scanner::start(
+            paths,
+            ScanOptions::from_settings(&self.settings),
+            self.app_state.scan_cache.clone(),
+        ));
+    }
+
+    /// Sorts the files by the table's sort keys, keeping the same files selected
+    fn sort_files(&mut self) {
+        let state = &mut self.app_state;
+        let order = sorted_order(&state.files, &self.settings.table_sort);
+
+        let mut new_index = vec![0; order.len()];
+        for (new, old) in order.iter().enumerate() {
+            new_index[*old] = new;
+        }
+
+        let mut files: Vec<Option<AudioFile>> = std::mem::take(&mut state.files)
+            .into_iter()
+            .map(Some)
+            .collect();
+        state.files = order.iter().filter_map(|i| files[*i].take()).collect();
+        state.table_selections = state
+            .table_selections
+            .iter()
+            .map(|i| new_index[*i])
+            .collect();
+        state.first_selection = state.first_selection.map(|i| new_index[i]);
+        state.last_selection = state.last_selection.map(|i| new_index[i]);
+
+        state.update_visible_rows();
+    }
+
+    /// Clicking a column sorts by it, clicking the current sort column again reverses it and
+    /// shift-clicking adds the column as another sort key
+    fn toggle_sort(&mut self, column: FileColumn, add_key: bool) {
+        let sort = &mut self.settings.table_sort;
+        let existing = sort.iter().position(|k| k.column == column);
+
+        match existing {
+            Some(i) if add_key || i == 0 => sort[i].ascending = !sort[i].ascending,
+            None if add_key => sort.push(SortKey {
+                column,
+                ascending: true,
+            }),
+            _ => {
+                *sort = vec![SortKey {
+                    column,
+                    ascending: true,
+                }]
+            }
+        }
+
+        self.sort_files();
+    }
+
+    /// Context menu actions apply to the whole selection, or just the clicked row if it isn't
+    /// selected
+    fn run_row_action(&mut self, i: usize, action: RowAction, ctx: &egui::Context) {
+        if !self.app_state.table_selections.contains(&i) {
+            self.app_state.select_only(i);
+        }
+
+        match action {
+            RowAction::Convert => {
+                let files = self.app_state.selected_files();
+                self.app_state
+                    .convert(files, &self.settings, &mut self.tasks_manager);
+            }
+            RowAction::ConvertWithSettings => {
+                self.app_state.selection_settings = Some(self.settings.clone());
+            }
+            RowAction::Remove => self.app_state.remove_selected(),
+            RowAction::ShowInfo => {
+                self.app_state.select_only(i);
+                ui::file_info::open_file_info_popup(ctx);
+            }
+            RowAction::OpenFolder => open_containing_folder(&self.app_state.files[i].path),
+        }
+    }
+
+    /// Moves probed files from the scanners into the table, sorting them once scanning finishes
+    fn receive_scanned_files(&mut self) {
+        if self.app_state.scanners.is_empty() {
+            return;
+        }
+
+        for scanner in &mut self.app_state.scanners {
+            let mut received = scanner.receive();
+            self.app_state.visible_rows_dirty |= !received.is_empty();
+            self.app_state.files.append(&mut received);
+            self.app_state.import_errors.append(&mut scanner.errors);
+
+            // opening a playlist again replaces it
+            for playlist in scanner.playlists.drain(..) {
+                let playlists = &mut self.app_state.playlists;
+                playlists.retain(|p| p.path != playlist.path);
+                playlists.push(playlist);
+            }
+        }
+        self.app_state.scanners.retain(|s| !s.is_finished());
+
+        if self.app_state.scanners.is_empty() {
+            self.sort_files();
+
+            self.app_state.showing_import_report = !self.app_state.import_errors.is_empty();
+
+            let scan_cache = self.app_state.scan_cache.clone();
+            std::thread::spawn(move || {
+                let _ = ScanCache::save(&scan_cache);
+            });
+        }
+    }
+
+    /// Decodes the selected files, or every file when none are selected, showing the results in the
+    /// Integrity column
+    fn verify_sources(&mut self) {
+        let paths: Vec<PathBuf> = match self.app_state.table_selections.is_empty() {
+            true => self
+                .app_state
+                .files
+                .iter()
+                .map(|f| f.path.clone())
+                .collect(),
+            false => self
+                .app_state
+                .selected_files()
+                .into_iter()
+                .map(|f| f.path)
+                .collect(),
+        };
+
+        if !self.settings.table_columns.contains(&FileColumn::Integrity) {
+            self.settings.table_columns.push(FileColumn::Integrity);
+        }
+        self.app_state.source_verifier = Some(SourceVerifier::start(paths));
+    }
+
+    fn receive_source_checks(&mut self) {
+        let Some(verifier) = &mut self.app_state.source_verifier else {
+            return;
+        };
+
+        for (path, result) in verifier.receive() {
+            for file in self.app_state.files.iter_mut().filter(|f| f.path == path) {
+                file.integrity = Some(result.clone());
+            }
+        }
+
+        if verifier.is_finished() {
+            self.app_state.source_verifier = None;
+        }
+    }
+
     fn file_table(&mut self, ui: &mut egui::Ui) {
         use egui_extras::{Column, TableBuilder};
 
@@ -163,71 +540,107 @@ impl AudioConverterApp {
             .size
             .max(ui.spacing().interact_size.y);
 
+        let columns = &self.settings.table_columns;
+        let sort = &self.settings.table_sort;
+
         let available_height = ui.available_height();
         let mut table = TableBuilder::new(ui)
             .striped(true)
             .resizable(true)
             .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
-            .column(Column::auto())
-            .column(Column::auto().at_least(75.0).resizable(true))
-            .column(Column::auto().at_least(75.0).resizable(true))
-            .column(Column::auto().at_least(75.0).resizable(true))
-            .column(Column::remainder().resizable(true))
             .min_scrolled_height(0.0)
             .max_scroll_height(available_height);
 
+        for (i, column) in columns.iter().enumerate() {
+            table = table.column(match column {
+                _ if i == columns.len() - 1 => Column::remainder().resizable(true),
+                FileColumn::Track => Column::auto(),
+                _ => Column::auto().at_least(75.0).resizable(true),
+            });
+        }
+
         table = table.sense(egui::Sense::click());
         let mut clicked_row: Option<usize> = None;
+        let mut clicked_header: Option<(FileColumn, bool)> = None;
+        let mut row_action: Option<(usize, RowAction)> = None;
 
         table
             .header(20.0, |mut header| {
-                header.col(|ui| {
-                    ui.strong("Track #");
-                });
-                header.col(|ui| {
-                    ui.strong("Artist");
-                });
-                header.col(|ui| {
-                    ui.strong("Album");
-                });
-                header.col(|ui| {
-                    ui.strong("Song Title");
-                });
-                header.col(|ui| {
-                    ui.strong("File Path");
-                });
+                for column in columns {
+                    header.col(|ui| {
+                        let mut text = column.label().to_string();
+                        if let Some(i) = sort.iter().position(|k| k.column == *column) {
+                            text += if sort[i].ascending { " ⏶" } else { " ⏷" };
+                            if sort.len() > 1 {
+                                text += &(i + 1).to_string();
+                            }
+                        }
+
+                        let response = ui.add(
+                            egui::Label::new(egui::RichText::new(text).strong())
+                                .sense(egui::Sense::click()),
+                        );
+                        if response.clicked() {
+                            clicked_header = Some((*column, ui.input(|i| i.modifiers.shift)));
+                        }
+                    });
+                }
             })
             .body(|body| {
                 let files = &self.app_state.files;
+                let visible_rows = &self.app_state.visible_rows;
                 let row_height = text_height;
-                let num_rows = files.len();
+                let num_rows = visible_rows.len();
                 body.rows(row_height, num_rows, |mut row| {
-                    if let Some(file) = files.get(row.index()) {
-                        row.set_selected(self.app_state.table_selections.contains(&row.index()));
+                    let index = visible_rows[row.index()];
+                    if let Some(file) = files.get(index) {
+                        row.set_selected(self.app_state.table_selections.contains(&index));
 
-                        row.col(|ui| {
-                            ui.label(file.track.as_deref().unwrap_or(""));
-                        });
-                        row.col(|ui| {
-                            ui.label(file.artist.as_deref().unwrap_or(NO_ARTIST));
-                        });
-                        row.col(|ui| {
-                            ui.label(file.album.as_deref().unwrap_or(NO_ALBUM));
-                        });
-                        row.col(|ui| {
-                            ui.label(file.title.as_deref().unwrap_or(NO_TITLE));
-                        });
-                        row.col(|ui| {
-                            ui.label(file.path.to_string_lossy());
-                        });
+                        for column in columns {
+                            row.col(|ui| {
+                                let damaged =
+                                    *column == FileColumn::Integrity && integrity_rank(file) < 2;
+                                match damaged {
+                                    true => ui.colored_label(
+                                        ui.visuals().error_fg_color,
+                                        cell_text(file, *column),
+                                    ),
+                                    false => ui.label(cell_text(file, *column)),
+                                };
+                            });
+                        }
 
-                        if row.response().clicked() {
-                            clicked_row = Some(row.index());
+                        let response = row.response();
+                        if response.clicked() {
+                            clicked_row = Some(index);
                         }
+
+                        response.context_menu(|ui| {
+                            let actions = [
+                                ("Convert", RowAction::Convert),
+                                ("Convert with settings...", RowAction::ConvertWithSettings),
+                                ("Remove", RowAction::Remove),
+                                ("Show info", RowAction::ShowInfo),
+                                ("Open containing folder", RowAction::OpenFolder),
+                            ];
+                            for (label, action) in actions {
+                                if ui.button(label).clicked() {
+                                    row_action = Some((index, action));
+                                }
+                            }
+                        });
                     }
                 });
             });
 
+        if let Some((column, add_key)) = clicked_header {
+            self.toggle_sort(column, add_key);
+        }
+
+        if let Some((i, action)) = row_action {
+            self.run_row_action(i, action, ui.ctx());
+        }
+
         // NOTE: this mess is for multi-select
         if let Some(i) = clicked_row {
             if self.app_state.first_selection.is_none() {
@@ -243,10 +656,17 @@ impl AudioConverterApp {
                     }
                 } else if self.app_state.first_selection.is_some() && input.modifiers.shift {
                     self.app_state.table_selections.clear();
-                    if let Some(start) = self.app_state.first_selection {
+                    // select the range of visible rows between the clicks
+                    let visible = &self.app_state.visible_rows;
+                    if let Some(start) = self.app_state.first_selection
+                        && let Ok(start) = visible.binary_search(&start)
+                        && let Ok(end) = visible.binary_search(&i)
+                    {
                         self.app_state
                             .table_selections
-                            .extend(start.min(i)..=start.max(i));
+                            .extend(&visible[start.min(end)..=start.max(end)]);
+                    } else {
+                        self.app_state.table_selections.insert(i);
                     }
                 } else {
                     if self.app_state.table_selections.len() == 1
@@ -265,11 +685,142 @@ impl AudioConverterApp {
                     self.app_state.last_selection = None;
                 }
             });
-            self.app_state.cover_art_rx = Some(self.app_state.files[i].load_album_art(Some(300)));
+            self.app_state.cover_art_rx = Some(
+                self.app_state.files[i].load_album_art(Some(300), &self.app_state.cover_art_cache),
+            );
         }
     }
 }
 
+/// A file being sorted, with the lowercased tag of each sort key that's a tag column
+struct SortEntry<'a> {
+    file: &'a AudioFile,
+    tags: Vec<Option<String>>,
+}
+
+/// The tag shown in the column, lowercased so sorting ignores case. None for columns that aren't
+/// tags
+fn sort_tag(file: &AudioFile, column: FileColumn) -> Option<String> {
+    let tag = match column {
+        FileColumn::Artist => file.artist.as_ref(),
+        // most files only have an artist tag, which is the album artist for those albums
+        FileColumn::AlbumArtist => file.album_artist.as_ref().or(file.artist.as_ref()),
+        FileColumn::Album => file.album.as_ref(),
+        FileColumn::Title => file.title.as_ref(),
+        _ => None,
+    };
+
+    tag.map(|t| t.to_lowercase())
+}
+
+/// Missing tags sort after every file that has one
+fn compare_tags(a: Option<&String>, b: Option<&String>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Unreadable files sort first, then damaged ones, intact ones and files that weren't checked
+fn integrity_rank(file: &AudioFile) -> u8 {
+    match &file.integrity {
+        Some(Err(_)) => 0,
+        Some(Ok(decoded)) if !decoded.is_intact() => 1,
+        Some(Ok(_)) => 2,
+        None => 3,
+    }
+}
+
+/// Compares two files by the column of the sort key at index `k`
+fn compare_entries(a: &SortEntry, b: &SortEntry, k: usize, column: FileColumn) -> Ordering {
+    let (x, y) = (&a.file.stream, &b.file.stream);
+    let (a, b, a_tag, b_tag) = (a.file, b.file, a.tags[k].as_ref(), b.tags[k].as_ref());
+
+    match column {
+        FileColumn::Track => a.track_number().cmp(&b.track_number()),
+        FileColumn::Disc => a.disc_number().cmp(&b.disc_number()),
+        FileColumn::Artist | FileColumn::AlbumArtist | FileColumn::Album | FileColumn::Title => {
+            compare_tags(a_tag, b_tag)
+        }
+        FileColumn::Path => a.path.cmp(&b.path),
+        FileColumn::Codec => x.codec.cmp(&y.codec),
+        FileColumn::SampleRate => x.sample_rate.cmp(&y.sample_rate),
+        FileColumn::BitDepth => x.bit_depth.cmp(&y.bit_depth),
+        FileColumn::Channels => x.channels.cmp(&y.channels),
+        FileColumn::Bitrate => x.bit_rate.cmp(&y.bit_rate),
+        FileColumn::Duration => x
+            .duration
+            .partial_cmp(&y.duration)
+            .unwrap_or(Ordering::Equal),
+        FileColumn::FileSize => x.file_size.cmp(&y.file_size),
+        FileColumn::Integrity => integrity_rank(a).cmp(&integrity_rank(b)),
+    }
+}
+
+/// Indices of the files in the order the sort keys put them in
+fn sorted_order(files: &[AudioFile], keys: &[SortKey]) -> Vec<usize> {
+    // tags are lowercased once per file rather than in every comparison
+    let entries: Vec<SortEntry> = files
+        .iter()
+        .map(|file| SortEntry {
+            file,
+            tags: keys.iter().map(|key| sort_tag(file, key.column)).collect(),
+        })
+        .collect();
+
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by(|a, b| {
+        let (a, b) = (&entries[*a], &entries[*b]);
+        keys.iter()
+            .enumerate()
+            .map(|(k, key)| match key.ascending {
+                true => compare_entries(a, b, k, key.column),
+                false => compare_entries(a, b, k, key.column).reverse(),
+            })
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+
+    order
+}
+
+fn cell_text(file: &AudioFile, column: FileColumn) -> String {
+    let stream = &file.stream;
+
+    match column {
+        FileColumn::Track => file.track.clone().unwrap_or_default(),
+        FileColumn::Disc => file.disc.clone().unwrap_or_default(),
+        FileColumn::Artist => file.artist.clone().unwrap_or(NO_ARTIST.to_string()),
+        FileColumn::AlbumArtist => file.album_artist.clone().unwrap_or_default(),
+        FileColumn::Album => file.album.clone().unwrap_or(NO_ALBUM.to_string()),
+        FileColumn::Title => file.title.clone().unwrap_or(NO_TITLE.to_string()),
+        FileColumn::Path => file.path.to_string_lossy().to_string(),
+        FileColumn::Codec => stream.codec.clone(),
+        FileColumn::SampleRate => audio_file::format_sample_rate(stream.sample_rate),
+        FileColumn::BitDepth => stream
+            .bit_depth
+            .map(|b| format!("{b}-bit"))
+            .unwrap_or_default(),
+        FileColumn::Channels => audio_file::format_channels(stream.channels),
+        FileColumn::Bitrate => stream
+            .bit_rate
+            .map(audio_file::format_bit_rate)
+            .unwrap_or_default(),
+        FileColumn::Duration => stream
+            .duration
+            .map(audio_file::format_duration)
+            .unwrap_or_default(),
+        FileColumn::FileSize => audio_file::format_file_size(stream.file_size),
+        FileColumn::Integrity => match &file.integrity {
+            Some(Ok(decoded)) => decoded.summary(),
+            Some(Err(e)) => format!("Unreadable: {e}"),
+            None => String::new(),
+        },
+    }
+}
+
 impl eframe::App for AudioConverterApp {
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         eframe::set_value(storage, eframe::APP_KEY, self);
@@ -278,6 +829,7 @@ impl eframe::App for AudioConverterApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.tasks_manager.update(&self.settings);
         self.app_state.is_transcoding = !self.tasks_manager.active_tasks.is_empty();
+        self.app_state.refresh_visible_rows();
 
         match self.settings.app_theme {
             AppTheme::System => ctx.set_visuals(egui::Visuals::default()),
@@ -293,15 +845,83 @@ impl eframe::App for AudioConverterApp {
             ui.heading("Settings");
             ui.separator();
             egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::CollapsingHeader::new("Presets").show(ui, |ui| {
+                    ui::presets::presets_section(&mut self.settings, &mut self.app_state, ui);
+                });
+                ui.separator();
+
                 ui::settings::settings_list(&mut self.settings, &self.app_state, ui);
 
                 ui.separator();
+                ui::targets::targets_section(&mut self.settings, ui);
+
+                ui.separator();
+
+                let filtered = self.app_state.filter.is_active();
+                ui.add_enabled(
+                    filtered,
+                    egui::Checkbox::new(
+                        &mut self.settings.run_convert_filtered_only,
+                        "Only convert filtered files",
+                    ),
+                );
+                ui.checkbox(
+                    &mut self.settings.run_skip_up_to_date,
+                    "Skip files that are already converted",
+                )
+                .on_hover_text_at_pointer(
+                    "Files converted with the same settings that haven't changed since, according to the manifest in the output directory",
+                );
+                ui.checkbox(
+                    &mut self.settings.run_verify_outputs,
+                    "Verify converted files",
+                )
+                .on_hover_text_at_pointer(
+                    "Decodes every output once written and fails the conversion if it's shorter or longer than the source, or is missing tags or cover art",
+                );
+
+                ui.horizontal(|ui| {
+                    if ui.button("Convert!").clicked() {
+                        let files: Vec<AudioFile> =
+                            if filtered && self.settings.run_convert_filtered_only {
+                                self.app_state
+                                    .visible_rows
+                                    .iter()
+                                    .map(|i| self.app_state.files[*i].clone())
+                                    .collect()
+                            } else {
+                                self.app_state.files.clone()
+                            };
+
+                        if !files.is_empty() {
+                            self.app_state
+                                .convert(files, &self.settings, &mut self.tasks_manager);
+                        }
+                    }
+
+                    ui::presets::preset_selector(&mut self.settings, ui);
+                });
 
-                if ui.button("Convert!").clicked() {
-                    for file in &self.app_state.files {
-                        self.tasks_manager.queue_audio_file(file.clone());
-                        self.app_state.is_transcoding = true;
+                ui.horizontal(|ui| {
+                    let has_selection = !self.app_state.table_selections.is_empty();
+                    if ui
+                        .add_enabled(has_selection, egui::Button::new("Convert selected"))
+                        .clicked()
+                    {
+                        let files = self.app_state.selected_files();
+                        self.app_state
+                            .convert(files, &self.settings, &mut self.tasks_manager);
+                    }
+                    if ui
+                        .add_enabled(has_selection, egui::Button::new("With other settings..."))
+                        .clicked()
+                    {
+                        self.app_state.selection_settings = Some(self.settings.clone());
                     }
+                });
+
+                if ui.button("Sync library...").clicked() {
+                    self.app_state.showing_sync = true;
                 }
             });
         });
@@ -314,39 +934,123 @@ impl eframe::App for AudioConverterApp {
 
                 if ui.button("Open files").clicked()
                     && let Some(paths) = rfd::FileDialog::new()
-                        .add_filter("audio", &crate::models::audio_file::ALLOWED_INPUT_TYPES)
+                        .add_filter("audio", &crate::models::audio_file::AUDIO_EXTENSIONS)
+                        .add_filter("playlists", &crate::playlist::PLAYLIST_EXTENSIONS)
+                        .add_filter("all files", &["*"])
                         .pick_files()
                 {
-                    for file in paths {
-                        let audio_file = AudioFile::new(file).unwrap();
-                        self.app_state.files.push(audio_file);
-                    }
+                    self.start_scan(paths);
                 }
 
                 if ui.button("Open folders").clicked()
-                    && let Some(paths) = rfd::FileDialog::new()
-                        .pick_folders()
+                    && let Some(paths) = rfd::FileDialog::new().pick_folders()
                 {
-                    for directory in &paths {
-                        let mut files = AudioFile::from_directory(directory).unwrap();
-                        self.app_state.files.append(&mut files);
+                    self.start_scan(paths);
+                }
+
+                if !self.app_state.files.is_empty() && ui.button("Clear all").clicked() {
+                    self.app_state.files.clear();
+                    self.app_state.visible_rows_dirty = true;
+                    self.app_state.playlists.clear();
+                    self.app_state.album_thumbnails.clear();
+                    self.app_state.table_selections.clear();
+                    self.app_state.first_selection = None;
+                    self.app_state.last_selection = None;
+                }
+
+                if !self.app_state.playlists.is_empty() {
+                    ui.menu_button(
+                        format!("Playlists ({})", self.app_state.playlists.len()),
+                        |ui| {
+                            ui::playlists::playlists_menu(&mut self.app_state.playlists, ui);
+                        },
+                    );
+                }
+
+                ui.selectable_value(&mut self.settings.table_view, FileView::Tracks, "Tracks");
+                ui.selectable_value(&mut self.settings.table_view, FileView::Albums, "Albums");
+
+                ui.menu_button("Columns", |ui| {
+                    ui::columns::columns_menu(&mut self.settings.table_columns, ui);
+
+                    ui.separator();
+                    if ui.button("Sort by album").clicked() {
+                        self.settings.table_sort = SortKey::album_order();
+                        self.sort_files();
+                    }
+                });
+
+                if !self.app_state.scanners.is_empty() {
+                    let (scanned, total) = self
+                        .app_state
+                        .scanners
+                        .iter()
+                        .fold((0, 0), |(scanned, total), scanner| {
+                            (scanned + scanner.scanned(), total + scanner.total())
+                        });
+
+                    ui.spinner();
+                    ui.add(
+                        egui::ProgressBar::new(scanned as f32 / total.max(1) as f32)
+                            .desired_width(150.0)
+                            .text(format!("Scanning {scanned}/{total}")),
+                    );
+                    if ui.button("Cancel").clicked() {
+                        for scanner in &self.app_state.scanners {
+                            scanner.cancel();
+                        }
                     }
                 }
 
-                if !self.app_state.files.is_empty() {
-                    if ui.button("Clear all").clicked()
-                    {
-                        self.app_state.files.clear();
-                        self.app_state.table_selections.clear();
-                        self.app_state.first_selection = None;
-                        self.app_state.last_selection = None;
+                match &self.app_state.source_verifier {
+                    Some(verifier) => {
+                        let (checked, total) = (verifier.checked(), verifier.total());
+
+                        ui.spinner();
+                        ui.add(
+                            egui::ProgressBar::new(checked as f32 / total.max(1) as f32)
+                                .desired_width(150.0)
+                                .text(format!("Verifying {checked}/{total}")),
+                        );
+                        if ui.button("Cancel").clicked() {
+                            verifier.cancel();
+                        }
+                    }
+                    None => {
+                        let verify_tooltip = "Decodes the selected files, or every file, to find damaged ones\n - FLAC files are also checked against their MD5";
+                        if ui
+                            .add_enabled(
+                                !self.app_state.files.is_empty(),
+                                egui::Button::new("Verify sources"),
+                            )
+                            .on_hover_text(verify_tooltip)
+                            .clicked()
+                        {
+                            self.verify_sources();
+                        }
                     }
                 }
             });
 
-            egui::ScrollArea::horizontal().show(ui, |ui| {
-                self.file_table(ui);
-            });
+            if !self.app_state.files.is_empty() {
+                ui::filter_bar::filter_bar(&mut self.app_state, ui);
+            }
+
+            match self.settings.table_view {
+                FileView::Tracks => {
+                    egui::ScrollArea::horizontal().show(ui, |ui| {
+                        self.file_table(ui);
+                    });
+                }
+                FileView::Albums => {
+                    ui::album_view::album_view(
+                        &mut self.app_state,
+                        &mut self.tasks_manager,
+                        &self.settings,
+                        ui,
+                    );
+                }
+            }
 
             if self.app_state.files.is_empty() {
                 ui.vertical_centered(|ui| {
@@ -370,59 +1074,161 @@ impl eframe::App for AudioConverterApp {
         }
 
         self.preview_dropped_files(ctx);
-        ctx.input(|i| {
-            if i.raw.dropped_files.is_empty() {
-                return;
-            }
-            for file in &i.raw.dropped_files {
-                let Some(path) = &file.path else { continue };
-                if path.is_dir() {
-                    let mut files = AudioFile::from_directory(path).unwrap();
-                    self.app_state.files.append(&mut files);
-                } else {
-                    self.app_state
-                        .files
-                        .push(AudioFile::new(path.clone()).unwrap());
-                }
-            }
+        let dropped_paths: Vec<PathBuf> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|f| f.path.clone())
+                .collect()
         });
+        if !dropped_paths.is_empty() {
+            self.start_scan(dropped_paths);
+        }
+
+        self.receive_scanned_files();
+        if !self.app_state.scanners.is_empty() {
+            ctx.request_repaint();
+        }
+
+        self.receive_source_checks();
+        if self.app_state.source_verifier.is_some() {
+            ctx.request_repaint();
+        }
 
-        if self.app_state.is_transcoding {
+        if self.app_state.is_transcoding
+            || !self.tasks_manager.results.is_empty()
+            || !self.tasks_manager.playlist_results.is_empty()
+        {
             ui::task_queue::task_queue_window(&mut self.tasks_manager, ctx);
         }
 
+        ui::selection_settings::selection_settings_window(
+            &mut self.app_state,
+            &mut self.tasks_manager,
+            ctx,
+        );
+
+        ui::confirm_conversion::confirm_conversion_window(
+            &mut self.app_state,
+            &mut self.tasks_manager,
+            ctx,
+        );
+        ui::confirm_conversion::conversion_error_window(&mut self.app_state, ctx);
+
+        ui::sync::sync_window(
+            &mut self.app_state,
+            &mut self.settings,
+            &mut self.tasks_manager,
+            ctx,
+        );
+
+        if self.app_state.showing_import_report {
+            ui::import_report::import_report_window(&mut self.app_state, ctx);
+        }
+
+        // keys typed into the search bar shouldn't delete or select files
+        let typing = ctx.wants_keyboard_input();
         ctx.input_mut(|input| {
-            if input.key_pressed(Key::Delete) {
-                if !self.app_state.table_selections.is_empty() {
-                    self.app_state.files = self
-                        .app_state
-                        .files
-                        .clone()
-                        .into_iter()
-                        .enumerate()
-                        .filter(|(i, _)| !self.app_state.table_selections.contains(i))
-                        .map(|(_, f)| f)
-                        .collect();
+            if typing {
+                return;
+            }
 
-                    self.app_state.table_selections.clear();
-                    self.app_state.first_selection = None;
-                    self.app_state.last_selection = None;
-                }
+            if input.key_pressed(Key::Delete) {
+                self.app_state.remove_selected();
             }
 
-            // select all
-            if !self.app_state.files.is_empty() {
+            // select all visible rows
+            if let Some(&first) = self.app_state.visible_rows.first() {
                 if input.consume_key(Modifiers::CTRL, Key::A) {
                     self.app_state.table_selections.clear();
                     self.app_state
                         .table_selections
-                        .extend(0..self.app_state.files.len());
-                    self.app_state.first_selection = Some(0);
-                    self.app_state.last_selection = Some(0);
-                    self.app_state.cover_art_rx =
-                        Some(self.app_state.files[0].load_album_art(Some(300))); // refresh cover art
+                        .extend(&self.app_state.visible_rows);
+                    self.app_state.first_selection = Some(first);
+                    self.app_state.last_selection = Some(first);
+                    self.app_state.cover_art_rx = Some(
+                        self.app_state.files[first]
+                            .load_album_art(Some(300), &self.app_state.cover_art_cache),
+                    ); // refresh cover art
                 }
             }
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(album_artist: Option<&str>, artist: &str, album: &str, track: &str) -> AudioFile {
+        AudioFile {
+            album_artist: album_artist.map(str::to_string),
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+            track: Some(track.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn album_order_ignores_case_and_falls_back_to_the_artist() {
+        let files = vec![
+            file(None, "beta", "B", "1"),
+            file(Some("Alpha"), "Guest", "A", "2/10"),
+            file(None, "alpha", "A", "1/10"),
+            file(None, "Beta", "A", "1"),
+        ];
+
+        assert_eq!(
+            sorted_order(&files, &SortKey::album_order()),
+            vec![2, 1, 3, 0]
+        );
+    }
+
+    #[test]
+    fn missing_tags_sort_last_and_keys_can_be_reversed() {
+        let files = vec![
+            AudioFile::default(),
+            file(None, "a", "x", "1"),
+            file(None, "b", "x", "1"),
+        ];
+        let by_artist = |ascending| {
+            vec![SortKey {
+                column: FileColumn::Artist,
+                ascending,
+            }]
+        };
+
+        assert_eq!(sorted_order(&files, &by_artist(true)), vec![1, 2, 0]);
+        assert_eq!(sorted_order(&files, &by_artist(false)), vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn selected_files_are_in_table_order_and_hidden_rows_are_deselected() {
+        let mut state = AudioConverterApp::default().app_state;
+        state.files = vec![
+            file(None, "Band", "Live", "1"),
+            file(None, "Band", "Studio", "1"),
+            file(None, "Other", "Live", "2"),
+        ];
+        state.table_selections = HashSet::from([2, 0]);
+        state.last_selection = Some(2);
+
+        let artists: Vec<_> = state
+            .selected_files()
+            .into_iter()
+            .map(|f| f.artist)
+            .collect();
+        assert_eq!(
+            artists,
+            vec![Some("Band".to_string()), Some("Other".to_string())]
+        );
+
+        state.filter.text = "band".to_string();
+        state.update_visible_rows();
+
+        assert_eq!(state.visible_rows, vec![0, 1]);
+        assert_eq!(state.table_selections, HashSet::from([0]));
+        assert_eq!(state.last_selection, Some(0));
+    }
+}
@@ -0,0 +1,89 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Gives the output the source's access and modification times, taken before it was converted
+pub fn copy_times(source: &fs::Metadata, output: &Path) -> io::Result<()> {
+    let mut times = fs::FileTimes::new();
+    if let Ok(accessed) = source.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = source.modified() {
+        times = times.set_modified(modified);
+    }
+
+    fs::File::options()
+        .write(true)
+        .open(output)?
+        .set_times(times)
+}
+
+/// Gives the output the source's read, write and execute bits. The owner can always write to it,
+/// a read-only output couldn't be replaced when the source is converted again
+#[cfg(unix)]
+pub fn copy_permissions(source: &fs::Metadata, output: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = source.permissions().mode() & 0o777 | 0o200;
+    fs::set_permissions(output, fs::Permissions::from_mode(mode))
+}
+
+/// Windows only has a read-only flag, which would stop the output from being replaced next time
+#[cfg(not(unix))]
+pub fn copy_permissions(_source: &fs::Metadata, _output: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Gives a mirrored directory the modification time of the library directory it mirrors, once
+/// nothing else is going to be written into it
+pub fn copy_directory_times(source: &Path, output: &Path) -> io::Result<()> {
+    let modified = fs::metadata(source)?.modified()?;
+    // opening a directory for writing isn't allowed, but its times can be set through any handle
+    fs::File::open(output)?.set_times(fs::FileTimes::new().set_modified(modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn outputs_get_the_source_modification_time() {
+        let temp = TempDir::new("copy-times");
+        let source = temp.path().join("a.flac");
+        let output = temp.path().join("a.opus");
+        fs::write(&source, b"source").unwrap();
+        fs::write(&output, b"output").unwrap();
+
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        copy_times(&fs::metadata(&source).unwrap(), &output).unwrap();
+
+        assert_eq!(fs::metadata(&output).unwrap().modified().unwrap(), modified);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn outputs_stay_writable_by_their_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new("copy-permissions");
+        let source = temp.path().join("a.flac");
+        let output = temp.path().join("a.opus");
+        fs::write(&source, b"source").unwrap();
+        fs::write(&output, b"output").unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o444)).unwrap();
+
+        copy_permissions(&fs::metadata(&source).unwrap(), &output).unwrap();
+
+        let mode = fs::metadata(&output).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode, 0o644);
+    }
+}
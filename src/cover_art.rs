@@ -0,0 +1,489 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use byteorder::{BigEndian, WriteBytesExt};
+use ffmpeg_next::format;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+
+use crate::cover_art_cache::CoverArtCache;
+use crate::models::settings::{CoverArtFormat, Settings};
+
+/// ID3v2 APIC picture types, FLAC uses the same numbering and FFmpeg stores the name in the
+/// `comment` tag of attached picture streams
+pub const PICTURE_TYPES: [&str; 21] = [
+    "Other",
+    "32x32 pixels 'file icon'",
+    "Other file icon",
+    "Cover (front)",
+    "Cover (back)",
+    "Leaflet page",
+    "Media (e.g. label side of CD)",
+    "Lead artist/lead performer/soloist",
+    "Artist/performer",
+    "Conductor",
+    "Band/Orchestra",
+    "Composer",
+    "Lyricist/text writer",
+    "Recording Location",
+    "During recording",
+    "During performance",
+    "Movie/video screen capture",
+    "A bright coloured fish",
+    "Illustration",
+    "Band/artist logotype",
+    "Publisher/Studio logotype",
+];
+
+pub const FRONT_COVER: u32 = 3;
+pub const OTHER: u32 = 0;
+
+const MIN_JPEG_QUALITY: u8 = 30;
+const JPEG_QUALITY_STEP: u8 = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CoverArtOptions {
+    pub resize: bool,
+    pub size: u32,
+    pub only_downscale: bool,
+    pub keep_aspect_ratio: bool,
+    pub format: CoverArtFormat,
+    pub jpeg_quality: u8,
+    pub max_bytes: Option<usize>,
+}
+
+impl CoverArtOptions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            resize: settings.out_enable_cover_art_resize,
+            size: settings.out_cover_art_resolution,
+            only_downscale: settings.out_cover_art_only_downscale,
+            keep_aspect_ratio: settings.out_cover_art_keep_aspect_ratio,
+            format: settings.out_cover_art_format.clone(),
+            jpeg_quality: settings.out_cover_art_jpeg_quality,
+            max_bytes: settings
+                .out_enable_cover_art_max_size
+                .then_some(settings.out_cover_art_max_size_kb * 1024),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Picture {
+    pub hash: u64,
+    pub picture_type: u32,
+    pub description: String,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub data: Vec<u8>,
+}
+
+impl Picture {
+    /// Reads the dimensions and colour depth from the image header without decoding the image
+    pub fn new(data: Vec<u8>, picture_type: u32, description: String) -> Option<Self> {
+        let reader = ImageReader::new(Cursor::new(&data))
+            .with_guessed_format()
+            .ok()?;
+        let mime_type = reader.format()?.to_mime_type().to_string();
+        let decoder = reader.into_decoder().ok()?;
+        let (width, height) = decoder.dimensions();
+        let depth = decoder.original_color_type().bits_per_pixel() as u32;
+
+        Some(Self {
+            hash: get_image_hash(&data),
+            picture_type,
+            description,
+            mime_type,
+            width,
+            height,
+            depth,
+            data,
+        })
+    }
+
+    pub fn decode(&self) -> Option<DynamicImage> {
+        ImageReader::new(Cursor::new(&self.data))
+            .with_guessed_format()
+            .ok()?
+            .decode()
+            .ok()
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        PICTURE_TYPES
+            .get(self.picture_type as usize)
+            .unwrap_or(&PICTURE_TYPES[OTHER as usize])
+    }
+
+    /// Re-encodes the picture according to the options, returning it untouched when no resize or
+    /// format change is needed and it already fits within the size limit
+    pub fn encode(
+        &self,
+        options: &CoverArtOptions,
+        allow_webp: bool,
+        cache: &CoverArtCache,
+    ) -> Option<Self> {
+        let needs_resize = options.resize
+            && (!options.only_downscale || self.width > options.size || self.height > options.size);
+
+        let original_format = ImageFormat::from_mime_type(&self.mime_type);
+        let format = match (&options.format, original_format) {
+            (CoverArtFormat::Keep, Some(f @ (ImageFormat::Jpeg | ImageFormat::Png))) => f,
+            (CoverArtFormat::Keep, Some(ImageFormat::WebP)) if allow_webp => ImageFormat::WebP,
+            (CoverArtFormat::Keep, _) => ImageFormat::Png,
+            (CoverArtFormat::JPEG, _) => ImageFormat::Jpeg,
+            (CoverArtFormat::PNG, _) => ImageFormat::Png,
+            (CoverArtFormat::WEBP, _) if allow_webp => ImageFormat::WebP,
+            (CoverArtFormat::WEBP, _) => ImageFormat::Jpeg,
+        };
+        let fits = options.max_bytes.is_none_or(|max| self.data.len() <= max);
+
+        if !needs_resize && original_format == Some(format) && fits {
+            return Some(self.clone());
+        }
+
+        let source = cache.decoded(self)?;
+        let resized;
+        let decoded = match (needs_resize, options.keep_aspect_ratio) {
+            (true, true) => {
+                resized = source.resize(options.size, options.size, FilterType::Lanczos3);
+                &resized
+            }
+            (true, false) => {
+                resized = source.resize_exact(options.size, options.size, FilterType::Lanczos3);
+                &resized
+            }
+            (false, _) => &*source,
+        };
+
+        let mut format = format;
+        let mut data = encode_image(decoded, format, options.jpeg_quality)?;
+
+        if let Some(max) = options.max_bytes
+            && data.len() > max
+        {
+            // lossless formats can't shrink any further, so fall back to stepping down JPEG quality
+            format = ImageFormat::Jpeg;
+            let mut quality = options.jpeg_quality;
+            loop {
+                data = encode_image(decoded, format, quality)?;
+                if data.len() <= max || quality <= MIN_JPEG_QUALITY {
+                    break;
+                }
+                quality = quality
+                    .saturating_sub(JPEG_QUALITY_STEP)
+                    .max(MIN_JPEG_QUALITY);
+            }
+        }
+
+        let depth = match format {
+            ImageFormat::Jpeg => 24,
+            _ => decoded.color().bits_per_pixel() as u32,
+        };
+
+        Some(Self {
+            hash: get_image_hash(&data),
+            picture_type: self.picture_type,
+            description: self.description.clone(),
+            mime_type: format.to_mime_type().to_string(),
+            width: decoded.width(),
+            height: decoded.height(),
+            depth,
+            data,
+        })
+    }
+
+    pub fn flac_picture_block(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+
+        let _ = buf.write_u32::<BigEndian>(self.picture_type);
+
+        let _ = buf.write_u32::<BigEndian>(self.mime_type.len() as u32);
+        buf.extend_from_slice(self.mime_type.as_bytes());
+
+        let _ = buf.write_u32::<BigEndian>(self.description.len() as u32);
+        buf.extend_from_slice(self.description.as_bytes());
+
+        let _ = buf.write_u32::<BigEndian>(self.width);
+        let _ = buf.write_u32::<BigEndian>(self.height);
+        let _ = buf.write_u32::<BigEndian>(self.depth);
+        // number of colours, only used by indexed images which the image crate always expands
+        let _ = buf.write_u32::<BigEndian>(0);
+
+        let _ = buf.write_u32::<BigEndian>(self.data.len() as u32);
+        buf.extend_from_slice(&self.data);
+
+        buf
+    }
+}
+
+pub fn get_image_hash(bytes: &[u8]) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn encode_image(image: &DynamicImage, format: ImageFormat, jpeg_quality: u8) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+
+    match format {
+        ImageFormat::Jpeg => {
+            JpegEncoder::new_with_quality(&mut data, jpeg_quality)
+                .encode_image(&image.to_rgb8())
+                .ok()?;
+        }
+        ImageFormat::WebP => {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+                .write_to(&mut Cursor::new(&mut data), format)
+                .ok()?;
+        }
+        _ => {
+            image.write_to(&mut Cursor::new(&mut data), format).ok()?;
+        }
+    }
+
+    Some(data)
+}
+
+pub fn picture_type_from_name(name: &str) -> Option<u32> {
+    PICTURE_TYPES
+        .iter()
+        .position(|t| t.eq_ignore_ascii_case(name))
+        .map(|i| i as u32)
+}
+
+/// Reads every attached picture in the file, keeping the picture type and description FFmpeg
+/// parsed from the FLAC picture blocks or ID3 APIC frames
+pub fn read_pictures(path: &Path) -> Result<Vec<Picture>, ffmpeg_next::Error> {
+    let mut input_ctx = format::input(path)?;
+
+    let mut pending: Vec<(usize, u32, String)> = input_ctx
+        .streams()
+        .filter(|s| {
+            s.parameters().medium() == ffmpeg_next::media::Type::Video
+                && s.disposition()
+                    .contains(ffmpeg_next::format::stream::Disposition::ATTACHED_PIC)
+        })
+        .enumerate()
+        .map(|(i, s)| {
+            let metadata = s.metadata();
+            let picture_type = metadata
+                .get("comment")
+                .and_then(picture_type_from_name)
+                .unwrap_or(if i == 0 { FRONT_COVER } else { OTHER });
+            let description = metadata.get("title").unwrap_or("").to_string();
+            (s.index(), picture_type, description)
+        })
+        .collect();
+
+    let mut pictures = Vec::new();
+    if pending.is_empty() {
+        return Ok(pictures);
+    }
+
+    // attached pictures are queued before any audio packets
+    for (s, packet) in input_ctx.packets() {
+        let Some(pos) = pending.iter().position(|(i, _, _)| *i == s.index()) else {
+            continue;
+        };
+        let (_, picture_type, description) = pending.remove(pos);
+
+        if let Some(data) = packet.data()
+            && let Some(picture) = Picture::new(data.to_vec(), picture_type, description)
+        {
+            pictures.push(picture);
+        }
+
+        if pending.is_empty() {
+            break;
+        }
+    }
+
+    Ok(pictures)
+}
+
+pub fn front_cover(pictures: &[Picture]) -> Option<&Picture> {
+    pictures
+        .iter()
+        .find(|p| p.picture_type == FRONT_COVER)
+        .or(pictures.first())
+}
+
+/// Writes the cover art next to the output files as `cover.jpg`/`cover.png`. An existing sidecar
+/// is left alone when it's the same picture, so an album's tracks write it once, and replaced
+/// when it differs, e.g. after the cover was changed
+pub fn write_sidecar(out_directory: &Path, picture: &Picture) -> std::io::Result<()> {
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_TEMPORARY: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = match picture.mime_type.as_str() {
+        "image/png" => "cover.png",
+        "image/webp" => "cover.webp",
+        _ => "cover.jpg",
+    };
+    let path = out_directory.join(file_name);
+
+    if fs::read(&path).is_ok_and(|existing| existing == picture.data) {
+        return Ok(());
+    }
+
+    // written aside and renamed over the sidecar, other tracks of the album may be writing it too
+    let temporary = out_directory.join(format!(
+        ".{file_name}.{}.tmp",
+        NEXT_TEMPORARY.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temporary, &picture.data)?;
+    fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::fs;
+
+    fn picture(mime_type: &str, data: &[u8]) -> Picture {
+        Picture {
+            hash: get_image_hash(data),
+            picture_type: FRONT_COVER,
+            description: String::new(),
+            mime_type: mime_type.to_string(),
+            width: 1,
+            height: 1,
+            depth: 24,
+            data: data.to_vec(),
+        }
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        encode_image(&DynamicImage::new_rgb8(width, height), ImageFormat::Png, 90).unwrap()
+    }
+
+    #[test]
+    fn pictures_have_their_real_dimensions() {
+        let picture = Picture::new(png(40, 20), FRONT_COVER, String::new()).unwrap();
+
+        assert_eq!(picture.mime_type, "image/png");
+        assert_eq!((picture.width, picture.height, picture.depth), (40, 20, 24));
+    }
+
+    #[test]
+    fn flac_picture_blocks_follow_the_specification() {
+        let picture = Picture::new(png(40, 20), FRONT_COVER, "Front".to_string()).unwrap();
+        let block = picture.flac_picture_block();
+        let word = |at: usize| u32::from_be_bytes(block[at..at + 4].try_into().unwrap());
+
+        assert_eq!(word(0), FRONT_COVER);
+        assert_eq!(word(4), 9);
+        assert_eq!(&block[8..17], b"image/png");
+        assert_eq!(word(17), 5);
+        assert_eq!(&block[21..26], b"Front");
+        // width, height, colour depth and the number of indexed colours
+        assert_eq!([word(26), word(30), word(34), word(38)], [40, 20, 24, 0]);
+        assert_eq!(word(42) as usize, picture.data.len());
+        assert_eq!(&block[46..], picture.data.as_slice());
+    }
+
+    fn options(format: CoverArtFormat) -> CoverArtOptions {
+        CoverArtOptions {
+            resize: false,
+            size: 10,
+            only_downscale: true,
+            keep_aspect_ratio: true,
+            format,
+            jpeg_quality: 90,
+            max_bytes: None,
+        }
+    }
+
+    #[test]
+    fn pictures_needing_no_change_are_kept() {
+        let picture = Picture::new(png(40, 20), FRONT_COVER, String::new()).unwrap();
+        let encoded = picture
+            .encode(&options(CoverArtFormat::Keep), false, &CoverArtCache::new())
+            .unwrap();
+
+        assert_eq!(encoded.data, picture.data);
+    }
+
+    #[test]
+    fn pictures_are_converted_to_the_chosen_format() {
+        let picture = Picture::new(png(40, 20), FRONT_COVER, String::new()).unwrap();
+        let cache = CoverArtCache::new();
+
+        let jpeg = picture
+            .encode(&options(CoverArtFormat::JPEG), false, &cache)
+            .unwrap();
+        assert_eq!(jpeg.mime_type, "image/jpeg");
+        assert_eq!((jpeg.width, jpeg.height, jpeg.depth), (40, 20, 24));
+
+        // only Vorbis comments take WebP
+        let webp = picture
+            .encode(&options(CoverArtFormat::WEBP), false, &cache)
+            .unwrap();
+        assert_eq!(webp.mime_type, "image/jpeg");
+    }
+
+    #[test]
+    fn pictures_are_resized_keeping_their_aspect_ratio() {
+        let picture = Picture::new(png(40, 20), FRONT_COVER, String::new()).unwrap();
+        let resize = CoverArtOptions {
+            resize: true,
+            ..options(CoverArtFormat::Keep)
+        };
+        let resized = picture
+            .encode(&resize, false, &CoverArtCache::new())
+            .unwrap();
+
+        assert_eq!((resized.width, resized.height), (10, 5));
+    }
+
+    #[test]
+    fn pictures_over_the_size_limit_fall_back_to_jpeg() {
+        let noise = image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([(x * 7 + y * 13) as u8, (x * y) as u8, (x ^ y) as u8])
+        });
+        let data = encode_image(&DynamicImage::ImageRgb8(noise), ImageFormat::Png, 90).unwrap();
+        let picture = Picture::new(data, FRONT_COVER, String::new()).unwrap();
+
+        let limited = CoverArtOptions {
+            max_bytes: Some(picture.data.len() - 1),
+            ..options(CoverArtFormat::PNG)
+        };
+        let encoded = picture
+            .encode(&limited, false, &CoverArtCache::new())
+            .unwrap();
+
+        assert_eq!(encoded.mime_type, "image/jpeg");
+    }
+
+    #[test]
+    fn sidecars_are_named_after_their_format() {
+        let temp = TempDir::new("sidecar-names");
+        write_sidecar(temp.path(), &picture("image/png", b"png")).unwrap();
+        write_sidecar(temp.path(), &picture("image/jpeg", b"jpeg")).unwrap();
+
+        assert_eq!(fs::read(temp.path().join("cover.png")).unwrap(), b"png");
+        assert_eq!(fs::read(temp.path().join("cover.jpg")).unwrap(), b"jpeg");
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn changed_cover_replaces_the_sidecar() {
+        let temp = TempDir::new("sidecar-changed");
+        write_sidecar(temp.path(), &picture("image/jpeg", b"old")).unwrap();
+        write_sidecar(temp.path(), &picture("image/jpeg", b"new")).unwrap();
+
+        assert_eq!(fs::read(temp.path().join("cover.jpg")).unwrap(), b"new");
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use image::DynamicImage;
+
+use crate::cover_art::{self, CoverArtOptions, Picture};
+use crate::models::audio_file::AlbumArtError;
+
+// a decoded 3000px cover is ~36MB, so only keep a handful around
+const MAX_DECODED_IMAGES: usize = 8;
+const MAX_THUMBNAILS: usize = 64;
+// encoded covers are usually resized, an album's worth of converting files shares one
+const MAX_ENCODED_PICTURES: usize = 32;
+const MAX_PATH_HASHES: usize = 4096;
+
+/// Filled in by whichever thread gets to it first, other threads asking for the same key wait on
+/// the result instead of doing the work again
+type Slot<T> = Arc<OnceLock<T>>;
+
+/// Map that evicts the oldest inserted entry once it reaches capacity
+struct BoundedMap<K, V> {
+    map: HashMap<K, V>,
+    order: VecDeque<K>,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone + Default> BoundedMap<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            map: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        self.map.get(key).cloned()
+    }
+
+    fn get_or_default(&mut self, key: K) -> V {
+        if let Some(v) = self.map.get(&key) {
+            return v.clone();
+        }
+
+        let v = V::default();
+        self.insert(key, v.clone());
+        v
+    }
+
+    fn insert(&mut self, key: K, v: V) {
+        if !self.map.contains_key(&key) {
+            if self.map.len() >= self.capacity
+                && let Some(oldest) = self.order.pop_front()
+            {
+                self.map.remove(&oldest);
+            }
+            self.order.push_back(key.clone());
+        }
+
+        self.map.insert(key, v);
+    }
+}
+
+/// A file as it was when its pictures were read, a changed file gets a new key
+type PathKey = (PathBuf, Option<SystemTime>, u64);
+
+fn path_key(path: &Path) -> PathKey {
+    let metadata = fs::metadata(path).ok();
+    (
+        path.to_path_buf(),
+        metadata.as_ref().and_then(|m| m.modified().ok()),
+        metadata.map(|m| m.len()).unwrap_or_default(),
+    )
+}
+
+/// Cover art shared by the UI previews and the transcoder, keyed by a hash of the picture data so
+/// an album's art is only decoded and re-encoded once no matter how many tracks it's in
+pub struct CoverArtCache {
+    decoded: Mutex<BoundedMap<u64, Slot<Option<Arc<DynamicImage>>>>>,
+    encoded: Mutex<BoundedMap<(u64, CoverArtOptions, bool), Slot<Option<Picture>>>>,
+    thumbnails: Mutex<BoundedMap<(u64, Option<u32>), Slot<Option<egui::ColorImage>>>>,
+    path_hashes: Mutex<BoundedMap<PathKey, u64>>,
+}
+
+impl CoverArtCache {
+    pub fn new() -> Self {
+        Self {
+            decoded: Mutex::new(BoundedMap::new(MAX_DECODED_IMAGES)),
+            encoded: Mutex::new(BoundedMap::new(MAX_ENCODED_PICTURES)),
+            thumbnails: Mutex::new(BoundedMap::new(MAX_THUMBNAILS)),
+            path_hashes: Mutex::new(BoundedMap::new(MAX_PATH_HASHES)),
+        }
+    }
+
+    pub fn decoded(&self, picture: &Picture) -> Option<Arc<DynamicImage>> {
+        let slot = self.decoded.lock().unwrap().get_or_default(picture.hash);
+        slot.get_or_init(|| picture.decode().map(Arc::new)).clone()
+    }
+
+    pub fn encoded(
+        &self,
+        picture: &Picture,
+        options: &CoverArtOptions,
+        allow_webp: bool,
+    ) -> Option<Picture> {
+        let slot = self.encoded.lock().unwrap().get_or_default((
+            picture.hash,
+            options.clone(),
+            allow_webp,
+        ));
+        let encoded = slot
+            .get_or_init(|| picture.encode(options, allow_webp, self))
+            .clone()?;
+
+        // the same image can be a different picture type in another file
+        Some(Picture {
+            picture_type: picture.picture_type,
+            description: picture.description.clone(),
+            ..encoded
+        })
+    }
+
+    /// Thumbnail of the front cover, files that were previewed before skip reading the pictures
+    pub fn thumbnail(
+        &self,
+        path: &Path,
+        size: Option<u32>,
+    ) -> Result<egui::ColorImage, AlbumArtError> {
+        let key = path_key(path);
+        let known_hash = self.path_hashes.lock().unwrap().get(&key);
+        if let Some(hash) = known_hash
+            && let Some(slot) = self.thumbnails.lock().unwrap().get(&(hash, size))
+            && let Some(Some(image)) = slot.get()
+        {
+            return Ok(image.clone());
+        }
+
+        let pictures = cover_art::read_pictures(path).map_err(|_| AlbumArtError::NotFound)?;
+        let cover = cover_art::front_cover(&pictures).ok_or(AlbumArtError::NotFound)?;
+        self.path_hashes.lock().unwrap().insert(key, cover.hash);
+
+        let slot = self
+            .thumbnails
+            .lock()
+            .unwrap()
+            .get_or_default((cover.hash, size));
+        slot.get_or_init(|| {
+            let decoded = self.decoded(cover)?;
+            let resized = match size {
+                Some(s) => decoded.thumbnail_exact(s, s).to_rgba8(),
+                None => decoded.to_rgba8(),
+            };
+
+            Some(egui::ColorImage::from_rgba_unmultiplied(
+                [resized.width() as usize, resized.height() as usize],
+                &resized,
+            ))
+        })
+        .clone()
+        .ok_or(AlbumArtError::DecodeFailed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_entry_is_evicted_at_capacity() {
+        let mut map = BoundedMap::new(2);
+        map.insert(1, "one");
+        map.insert(2, "two");
+        // replacing an entry doesn't make room
+        map.insert(1, "uno");
+        map.insert(3, "three");
+
+        assert_eq!(map.get(&1), None);
+        assert_eq!(map.get(&2), Some("two"));
+        assert_eq!(map.get(&3), Some("three"));
+    }
+
+    #[test]
+    fn default_slots_are_shared() {
+        let mut map: BoundedMap<u64, Slot<u32>> = BoundedMap::new(2);
+        map.get_or_default(1).get_or_init(|| 42);
+
+        assert_eq!(map.get_or_default(1).get(), Some(&42));
+    }
+}
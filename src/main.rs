@@ -1,17 +1,96 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 mod app;
+mod attributes;
+mod cover_art;
+mod cover_art_cache;
+mod manifest;
 mod models;
+mod playlist;
+mod scan_cache;
+mod scanner;
+mod source_handling;
+mod sync;
 mod tasks_manager;
+#[cfg(test)]
+mod test_support;
 mod transcode;
 mod ui;
+mod verify;
+mod watch;
+mod worker_pool;
+
+use std::path::Path;
 
 use app::AudioConverterApp;
+use manifest::Manifest;
+use watch::WatchOptions;
 
 use eframe::egui;
 
+pub const APP_NAME: &str = "Batch Audio File Converter";
+
+fn print_manifest(args: &[String]) -> Result<(), String> {
+    let [directory] = args else {
+        return Err("Usage: --print-manifest <output directory>".to_string());
+    };
+
+    let directory = Path::new(directory);
+    let manifest = Manifest::read(directory)
+        .map_err(|e| format!("Couldn't read the manifest in {}: {e}", directory.display()))?;
+    manifest.print(directory);
+
+    Ok(())
+}
+
+/// Release builds on Windows have no console of their own, so the command line options print to
+/// the one they were started from
+#[cfg(all(windows, not(debug_assertions)))]
+fn attach_parent_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// Runs the command line options without opening the window, None when there are none
+fn run_without_window(args: &[String]) -> Option<Result<(), String>> {
+    let (option, args) = args.split_first()?;
+
+    match option.as_str() {
+        "--print-manifest" => Some(print_manifest(args)),
+        "--watch" => Some(
+            WatchOptions::parse(args)
+                .map_err(|e| format!("{e}\nUsage: {}", watch::USAGE))
+                .and_then(watch::run),
+        ),
+        _ => None,
+    }
+}
+
 fn main() -> eframe::Result {
     env_logger::init();
+    ffmpeg_next::init().expect("Failed to initialise FFmpeg");
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    #[cfg(all(windows, not(debug_assertions)))]
+    if !args.is_empty() {
+        attach_parent_console();
+    }
+    if let Some(result) = run_without_window(&args) {
+        if let Err(e) = result {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([640.0, 400.0])
@@ -19,10 +98,8 @@ fn main() -> eframe::Result {
         ..Default::default()
     };
 
-    ffmpeg_next::init().expect("Failed to initialise FFmpeg");
-
     eframe::run_native(
-        "Batch Audio File Converter",
+        APP_NAME,
         options,
         Box::new(|cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
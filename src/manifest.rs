@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::models::preset::Preset;
+use crate::models::settings::{Settings, SourceAction};
+use crate::transcode;
+
+const MANIFEST_VERSION: u32 = 3;
+/// Kept entries by source, so targets sharing the output directory replaced each other's
+const SOURCE_KEYED_VERSION: u32 = 2;
+/// Kept in the root of the output directory so it travels with the converted files
+const MANIFEST_FILE: &str = ".audio-converter-manifest.ron";
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// What a converted file was produced from and how
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct ManifestEntry {
+    #[serde(default)]
+    pub source: PathBuf,
+    /// Relative to the output directory, so the drive can be mounted somewhere else
+    pub output: PathBuf,
+    pub source_size: u64,
+    pub source_modified: Option<SystemTime>,
+    pub source_hash: u64,
+    /// The output settings, named after the preset they match or "Custom"
+    pub preset: Preset,
+    pub settings_hash: u64,
+    pub encoder: String,
+    pub converted: SystemTime,
+}
+
+impl ManifestEntry {
+    /// Takes the source's metadata from before converting, the source may have been moved since
+    pub fn new(
+        metadata: &fs::Metadata,
+        source_hash: u64,
+        output: &Path,
+        directory: &Path,
+        settings: &Settings,
+    ) -> Self {
+        let name = Preset::matching(settings)
+            .map(|p| p.name)
+            .unwrap_or("Custom".to_string());
+
+        Self {
+            source: PathBuf::new(),
+            output: output
+                .strip_prefix(directory)
+                .unwrap_or(output)
+                .to_path_buf(),
+            source_size: metadata.len(),
+            source_modified: metadata.modified().ok(),
+            source_hash,
+            preset: Preset::from_settings(&name, settings),
+            settings_hash: settings_hash(settings),
+            encoder: transcode::encoder_version(&settings.out_codec),
+            converted: SystemTime::now(),
+        }
+    }
+}
+
+/// The files converted into an output directory, keyed by their path relative to it. A source
+/// converted by several targets sharing the directory has an entry per output
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct Manifest {
+    version: u32,
+    pub entries: HashMap<PathBuf, ManifestEntry>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn read(directory: &Path) -> Result<Self, ron::Error> {
+        let file = fs::File::open(directory.join(MANIFEST_FILE))?;
+        Ok(ron::de::from_reader(BufReader::new(file))?)
+    }
+
+    /// An empty manifest when the directory doesn't have one yet, it can't be read or it was
+    /// written by an older version. Entries keyed by source are moved under their output
+    pub fn load(directory: &Path) -> Self {
+        match Self::read(directory) {
+            Ok(manifest) if manifest.version == MANIFEST_VERSION => manifest,
+            Ok(manifest) if manifest.version == SOURCE_KEYED_VERSION => Self {
+                version: MANIFEST_VERSION,
+                entries: manifest
+                    .entries
+                    .into_iter()
+                    .map(|(source, entry)| {
+                        (entry.output.clone(), ManifestEntry { source, ..entry })
+                    })
+                    .collect(),
+            },
+            _ => Self::default(),
+        }
+    }
+
+    /// Written to a temporary file that replaces the manifest, so it's never left half written
+    pub fn save(&self, directory: &Path) -> Result<(), ron::Error> {
+        fs::create_dir_all(directory)?;
+        let path = directory.join(MANIFEST_FILE);
+        let temporary = path.with_extension("ron.tmp");
+
+        let mut writer = BufWriter::new(fs::File::create(&temporary)?);
+        ron::Options::default().to_io_writer_pretty(&mut writer, self, Default::default())?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+
+        Ok(fs::rename(temporary, path)?)
+    }
+
+    /// Whether `output` in `directory` was converted from the source with the same settings and
+    /// the source hasn't changed since. The source is only hashed when its size or modification
+    /// time differ, if the content turns out to be the same the entry's modification time is
+    /// refreshed
+    pub fn is_up_to_date(
+        &mut self,
+        source: &Path,
+        output: &Path,
+        directory: &Path,
+        settings_hash: u64,
+    ) -> bool {
+        let Some(entry) = self
+            .entries
+            .get_mut(output.strip_prefix(directory).unwrap_or(output))
+        else {
+            return false;
+        };
+        if entry.source != source || entry.settings_hash != settings_hash || !output.exists() {
+            return false;
+        }
+        let Ok(metadata) = fs::metadata(source) else {
+            return false;
+        };
+
+        if entry.source_size != metadata.len() {
+            return false;
+        }
+        if entry.source_modified == metadata.modified().ok() {
+            return true;
+        }
+
+        // touched without being changed, e.g. restored from a backup
+        if hash_file(source).is_ok_and(|hash| hash == entry.source_hash) {
+            entry.source_modified = metadata.modified().ok();
+            return true;
+        }
+
+        false
+    }
+
+    /// Records the entry under its output, replacing whatever was converted to it before
+    pub fn insert(&mut self, source: &Path, entry: ManifestEntry) {
+        self.entries.insert(
+            entry.output.clone(),
+            ManifestEntry {
+                source: source.to_path_buf(),
+                ..entry
+            },
+        );
+    }
+
+    pub fn remove(&mut self, output: &Path, directory: &Path) {
+        self.entries
+            .remove(output.strip_prefix(directory).unwrap_or(output));
+    }
+
+    /// Every recorded source with the files it was converted to in `directory`
+    pub fn outputs_by_source(&self, directory: &Path) -> HashMap<PathBuf, Vec<PathBuf>> {
+        let mut outputs: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+        for entry in self.entries.values() {
+            outputs
+                .entry(entry.source.clone())
+                .or_default()
+                .push(directory.join(&entry.output));
+        }
+
+        outputs
+    }
+
+    /// Sources under `source_root` that no longer exist, along with the file they were converted to
+    pub fn orphans(&self, source_root: &Path, directory: &Path) -> Vec<(PathBuf, PathBuf)> {
+        let mut orphans: Vec<(PathBuf, PathBuf)> = self
+            .entries
+            .values()
+            .filter(|entry| entry.source.starts_with(source_root) && !entry.source.exists())
+            .map(|entry| (entry.source.clone(), directory.join(&entry.output)))
+            .collect();
+        orphans.sort();
+
+        orphans
+    }
+
+    /// Prints every entry, sorted by source and output, for the `--print-manifest` option
+    pub fn print(&self, directory: &Path) {
+        let mut entries: Vec<&ManifestEntry> = self.entries.values().collect();
+        entries.sort_by_key(|entry| (&entry.source, &entry.output));
+
+        for entry in entries {
+            println!("{}", entry.source.display());
+            println!("  output:    {}", directory.join(&entry.output).display());
+            println!("  hash:      {:016x}", entry.source_hash);
+            println!("  preset:    {}", entry.preset.name);
+            println!(
+                "  settings:  {}",
+                ron::ser::to_string(&entry.preset).unwrap_or_default()
+            );
+            println!("  encoder:   {}", entry.encoder);
+            println!("  converted: {} UTC", format_timestamp(entry.converted));
+        }
+    }
+}
+
+/// ISO 8601 UTC date and time like "2024-05-01T18:30:00", without pulling in a date crate. Days
+/// are converted to a civil date with Howard Hinnant's algorithm
+pub fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{year}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// FNV-1a of the file's content, unlike `DefaultHasher` it stays the same across Rust versions so
+/// it can be stored
+pub fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut file = fs::File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut hash = FNV_OFFSET;
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hash = fnv1a(hash, &buffer[..read]);
+    }
+
+    Ok(hash)
+}
+
+/// Hash of the output options, everything a preset stores except what happens to the sources, so
+/// changing any of them makes the converted files out of date
+pub fn settings_hash(settings: &Settings) -> u64 {
+    let preset = Preset {
+        source_action: SourceAction::Keep,
+        source_folder: String::new(),
+        ..Preset::from_settings("", settings)
+    };
+    let serialized = ron::ser::to_string(&preset).unwrap_or_default();
+    fnv1a(FNV_OFFSET, serialized.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::audio_file::AudioCodec;
+    use crate::test_support::TempDir;
+    use std::time::Duration;
+
+    fn entry(output: &str, source: &Path, settings: &Settings) -> ManifestEntry {
+        let metadata = fs::metadata(source).unwrap();
+        ManifestEntry::new(
+            &metadata,
+            hash_file(source).unwrap(),
+            Path::new(output),
+            Path::new(""),
+            settings,
+        )
+    }
+
+    #[test]
+    fn up_to_date_when_unchanged() {
+        let temp = TempDir::new("manifest-unchanged");
+        let directory = temp.path();
+        let source = directory.join("a.flac");
+        fs::write(&source, b"audio").unwrap();
+        fs::write(directory.join("a.ogg"), b"converted").unwrap();
+
+        let settings = Settings::default();
+        let hash = settings_hash(&settings);
+        let output = directory.join("a.ogg");
+        let mut manifest = Manifest::default();
+        manifest.insert(&source, entry("a.ogg", &source, &settings));
+
+        assert!(manifest.is_up_to_date(&source, &output, directory, hash));
+        assert!(!manifest.is_up_to_date(&source, &output, directory, hash + 1));
+        assert!(!manifest.is_up_to_date(&directory.join("b.flac"), &output, directory, hash));
+        assert!(!manifest.is_up_to_date(&source, &directory.join("a.mp3"), directory, hash));
+
+        fs::remove_file(&output).unwrap();
+        assert!(!manifest.is_up_to_date(&source, &output, directory, hash));
+    }
+
+    #[test]
+    fn touched_source_with_the_same_content_is_up_to_date() {
+        let temp = TempDir::new("manifest-touched");
+        let directory = temp.path();
+        let source = directory.join("a.flac");
+        fs::write(&source, b"audio").unwrap();
+        fs::write(directory.join("a.ogg"), b"converted").unwrap();
+
+        let settings = Settings::default();
+        let hash = settings_hash(&settings);
+        let mut manifest = Manifest::default();
+        let mut recorded = entry("a.ogg", &source, &settings);
+        recorded.source_modified = Some(UNIX_EPOCH + Duration::from_secs(1));
+        manifest.insert(&source, recorded);
+
+        assert!(manifest.is_up_to_date(&source, &directory.join("a.ogg"), directory, hash));
+        // the new modification time is remembered, so the source isn't hashed again
+        assert_eq!(
+            manifest.entries[Path::new("a.ogg")].source_modified,
+            fs::metadata(&source).unwrap().modified().ok()
+        );
+    }
+
+    #[test]
+    fn changed_source_of_the_same_size_is_out_of_date() {
+        let temp = TempDir::new("manifest-changed");
+        let directory = temp.path();
+        let source = directory.join("a.flac");
+        fs::write(&source, b"audio").unwrap();
+        fs::write(directory.join("a.ogg"), b"converted").unwrap();
+
+        let settings = Settings::default();
+        let hash = settings_hash(&settings);
+        let mut manifest = Manifest::default();
+        let mut recorded = entry("a.ogg", &source, &settings);
+        recorded.source_modified = Some(UNIX_EPOCH + Duration::from_secs(1));
+        manifest.insert(&source, recorded);
+
+        fs::write(&source, b"AUDIO").unwrap();
+        assert!(!manifest.is_up_to_date(&source, &directory.join("a.ogg"), directory, hash));
+    }
+
+    #[test]
+    fn targets_sharing_a_directory_keep_their_own_entries() {
+        let temp = TempDir::new("manifest-shared");
+        let directory = temp.path();
+        let source = directory.join("a.flac");
+        fs::write(&source, b"audio").unwrap();
+        fs::write(directory.join("a.ogg"), b"converted").unwrap();
+        fs::write(directory.join("a.mp3"), b"converted").unwrap();
+
+        let vorbis = Settings::default();
+        let mp3 = Settings {
+            out_codec: AudioCodec::MP3,
+            ..vorbis.clone()
+        };
+        let mut manifest = Manifest::default();
+        manifest.insert(&source, entry("a.ogg", &source, &vorbis));
+        manifest.insert(&source, entry("a.mp3", &source, &mp3));
+
+        assert!(manifest.is_up_to_date(
+            &source,
+            &directory.join("a.ogg"),
+            directory,
+            settings_hash(&vorbis)
+        ));
+        assert!(manifest.is_up_to_date(
+            &source,
+            &directory.join("a.mp3"),
+            directory,
+            settings_hash(&mp3)
+        ));
+        assert_eq!(manifest.outputs_by_source(directory)[&source].len(), 2);
+    }
+
+    #[test]
+    fn source_keyed_manifests_are_moved_under_their_outputs() {
+        let temp = TempDir::new("manifest-migrated");
+        let directory = temp.path();
+        let source = directory.join("a.flac");
+        fs::write(&source, b"audio").unwrap();
+
+        let mut manifest = Manifest {
+            version: SOURCE_KEYED_VERSION,
+            entries: HashMap::new(),
+        };
+        let settings = Settings::default();
+        manifest
+            .entries
+            .insert(source.clone(), entry("a.ogg", &source, &settings));
+        manifest.save(directory).unwrap();
+
+        let loaded = Manifest::load(directory);
+        assert_eq!(loaded.version, MANIFEST_VERSION);
+        assert_eq!(loaded.entries[Path::new("a.ogg")].source, source);
+    }
+
+    #[test]
+    fn orphans_are_missing_sources_under_the_root() {
+        let temp = TempDir::new("manifest-orphans");
+        let directory = temp.path();
+        let library = directory.join("library");
+        fs::create_dir_all(library.join("album")).unwrap();
+        let kept = library.join("album/kept.flac");
+        fs::write(&kept, b"audio").unwrap();
+
+        let settings = Settings::default();
+        let mut manifest = Manifest::default();
+        manifest.insert(&kept, entry("album/kept.ogg", &kept, &settings));
+        for (source, output) in [
+            (library.join("album/removed.flac"), "album/removed.ogg"),
+            (directory.join("elsewhere/other.flac"), "other.ogg"),
+        ] {
+            manifest.insert(&source, entry(output, &kept, &settings));
+        }
+
+        let out = Path::new("/out");
+        assert_eq!(
+            manifest.orphans(&library, out),
+            vec![(
+                library.join("album/removed.flac"),
+                out.join("album/removed.ogg")
+            )]
+        );
+    }
+
+    #[test]
+    fn timestamps_are_utc_dates() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00");
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00"
+        );
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(1_714_588_200)),
+            "2024-05-01T18:30:00"
+        );
+    }
+
+    #[test]
+    fn settings_hash_ignores_what_happens_to_sources() {
+        let settings = Settings::default();
+        let hash = settings_hash(&settings);
+
+        let moved = Settings {
+            out_source_action: SourceAction::Delete,
+            out_source_folder: "done".to_string(),
+            out_directory: "elsewhere".to_string(),
+            ..settings.clone()
+        };
+        assert_eq!(settings_hash(&moved), hash);
+
+        let recoded = Settings {
+            out_codec: AudioCodec::FLAC,
+            ..settings.clone()
+        };
+        assert_ne!(settings_hash(&recoded), hash);
+
+        let smaller = Settings {
+            out_bitrate: settings.out_bitrate / 2,
+            ..settings
+        };
+        assert_ne!(settings_hash(&smaller), hash);
+    }
+}
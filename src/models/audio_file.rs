@@ -1,10 +1,26 @@
+use ffmpeg_next::ffi::{AV_CODEC_PROP_LOSSLESS, avcodec_descriptor_get};
 use ffmpeg_next::{format, media};
-use image::{ImageBuffer, Rgba};
+use std::fmt;
 use std::path::PathBuf;
-use std::sync::mpsc;
+use std::sync::{Arc, mpsc};
 use std::thread;
 
-pub const ALLOWED_INPUT_TYPES: [&str; 7] = ["flac", "mp3", "ogg", "wav", "opus", "aac", "m4a"];
+use crate::cover_art_cache::CoverArtCache;
+use crate::verify::Decoded;
+
+/// Extensions that are probed without further checks, anything else is only probed when
+/// `import_probe_unknown` is on
+pub const AUDIO_EXTENSIONS: [&str; 28] = [
+    "flac", "mp3", "ogg", "oga", "wav", "opus", "aac", "m4a", "wv", "ape", "aiff", "aif", "aifc",
+    "alac", "mka", "dsf", "dff", "tta", "wma", "mpc", "caf", "ac3", "dts", "spx", "w64", "au",
+    "amr", "mp2",
+];
+
+/// Common files found next to audio that are never worth opening with FFmpeg
+pub const NON_AUDIO_EXTENSIONS: [&str; 22] = [
+    "jpg", "jpeg", "png", "gif", "bmp", "webp", "txt", "log", "cue", "nfo", "pdf", "m3u", "m3u8",
+    "pls", "md5", "sfv", "accurip", "db", "ini", "json", "xml", "lrc",
+];
 
 #[derive(Debug)]
 pub enum AlbumArtError {
@@ -12,12 +28,46 @@ pub enum AlbumArtError {
     DecodeFailed,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AudioFileError {
     NotAnAudioFile,
-    NotADirectory,
-    NoExtension,
     InputError,
+    NotFound,
+    PermissionDenied,
+    CorruptHeader,
+    NoAudioStream,
+    UnsupportedCodec(String),
+}
+
+impl AudioFileError {
+    /// Entries that simply aren't audio and are skipped without being reported
+    pub fn is_ignorable(&self) -> bool {
+        matches!(self, AudioFileError::NotAnAudioFile)
+    }
+}
+
+impl From<std::io::Error> for AudioFileError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => AudioFileError::NotFound,
+            std::io::ErrorKind::PermissionDenied => AudioFileError::PermissionDenied,
+            _ => AudioFileError::InputError,
+        }
+    }
+}
+
+impl fmt::Display for AudioFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioFileError::NotAnAudioFile => write!(f, "Not an audio file"),
+            AudioFileError::InputError => write!(f, "Could not read file"),
+            AudioFileError::NotFound => write!(f, "File not found"),
+            AudioFileError::PermissionDenied => write!(f, "Permission denied"),
+            AudioFileError::CorruptHeader => write!(f, "Corrupt or unrecognised header"),
+            AudioFileError::NoAudioStream => write!(f, "No audio stream"),
+            AudioFileError::UnsupportedCodec(codec) => write!(f, "Unsupported codec: {codec}"),
+        }
+    }
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Eq, Clone)]
@@ -45,13 +95,85 @@ pub enum AudioSampleRate {
     HiRes96,
 }
 
-#[derive(Clone, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default)]
+pub struct StreamInfo {
+    pub codec: String,
+    pub lossless: bool,
+    pub sample_rate: u32,
+    pub bit_depth: Option<u32>,
+    pub channels: u32,
+    pub bit_rate: Option<u64>,
+    pub duration: Option<f64>,
+    pub file_size: u64,
+}
+
+impl StreamInfo {
+    fn new(
+        input_ctx: &format::context::Input,
+        stream: &format::stream::Stream,
+        file_size: u64,
+    ) -> Self {
+        let parameters = stream.parameters();
+        let codec_id = parameters.id();
+        let (sample_rate, channels, raw_bits, coded_bits, stream_bit_rate) = unsafe {
+            let par = &*parameters.as_ptr();
+            (
+                par.sample_rate,
+                par.ch_layout.nb_channels,
+                par.bits_per_raw_sample,
+                par.bits_per_coded_sample,
+                par.bit_rate,
+            )
+        };
+        let lossless = unsafe {
+            let descriptor = avcodec_descriptor_get(codec_id.into());
+            !descriptor.is_null() && (*descriptor).props & AV_CODEC_PROP_LOSSLESS as i32 != 0
+        };
+
+        // lossy codecs have no bit depth, PCM only sets the coded depth
+        let bit_depth = [raw_bits, coded_bits]
+            .into_iter()
+            .find(|b| *b > 0)
+            .filter(|_| lossless)
+            .map(|b| b as u32);
+        let bit_rate = [stream_bit_rate, input_ctx.bit_rate()]
+            .into_iter()
+            .find(|b| *b > 0)
+            .map(|b| b as u64);
+        let duration = (input_ctx.duration() > 0)
+            .then(|| input_ctx.duration() as f64 / ffmpeg_next::ffi::AV_TIME_BASE as f64);
+
+        Self {
+            codec: codec_id.name().to_string(),
+            lossless,
+            sample_rate: sample_rate.max(0) as u32,
+            bit_depth,
+            channels: channels.max(0) as u32,
+            bit_rate,
+            duration,
+            file_size,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
 pub struct AudioFile {
     pub path: PathBuf,
     pub artist: Option<String>,
+    pub album_artist: Option<String>,
     pub album: Option<String>,
     pub title: Option<String>,
     pub track: Option<String>,
+    pub disc: Option<String>,
+    pub has_cover_art: bool,
+    pub stream: StreamInfo,
+    /// The folder the file was imported from, to keep its path relative to it when moving the
+    /// source. Not cached, the same file can be imported from another folder
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+    /// The result of the last "Verify sources", or why the file couldn't be decoded
+    #[serde(skip)]
+    pub integrity: Option<Result<Decoded, String>>,
 }
 
 impl Default for AudioFile {
@@ -59,112 +181,86 @@ impl Default for AudioFile {
         Self {
             path: Default::default(),
             artist: Default::default(),
+            album_artist: Default::default(),
             album: Default::default(),
             title: Default::default(),
             track: Default::default(),
+            disc: Default::default(),
+            has_cover_art: Default::default(),
+            stream: Default::default(),
+            root: Default::default(),
+            integrity: Default::default(),
         }
     }
 }
 
 impl AudioFile {
+    /// Probes the file with FFmpeg, succeeding for anything with a decodable audio stream
     pub fn new(path: PathBuf) -> Result<Self, AudioFileError> {
-        let Some(path_ext) = &path.extension() else {
-            return Err(AudioFileError::NoExtension);
-        };
-        let Some(path_ext) = &path_ext.to_str() else {
-            return Err(AudioFileError::InputError);
-        };
-        if !ALLOWED_INPUT_TYPES.contains(path_ext) {
-            return Err(AudioFileError::NotAnAudioFile);
+        // FFmpeg only reports a generic error for unreadable files, so check with std first
+        let file_size = std::fs::File::open(&path)?.metadata()?.len();
+
+        let input_ctx = format::input(&path).map_err(|e| match e {
+            ffmpeg_next::Error::InvalidData => AudioFileError::CorruptHeader,
+            _ => AudioFileError::InputError,
+        })?; // TODO: loading metadata makes up all the loading time, its instant without it
+
+        let stream = input_ctx
+            .streams()
+            .best(media::Type::Audio)
+            .ok_or(AudioFileError::NoAudioStream)?;
+        let codec_id = stream.parameters().id();
+        if ffmpeg_next::decoder::find(codec_id).is_none() {
+            return Err(AudioFileError::UnsupportedCodec(
+                codec_id.name().to_string(),
+            ));
         }
 
-        let input_ctx = format::input(&path).expect("Invalid path provided to FFmpeg"); // TODO: loading metadata makes up all the loading time, its instant without it
+        let stream = StreamInfo::new(&input_ctx, &stream, file_size);
+        let has_cover_art = input_ctx.streams().any(|s| {
+            s.disposition()
+                .contains(format::stream::Disposition::ATTACHED_PIC)
+        });
 
         return Ok(Self {
             path: path,
             artist: get_tag(&input_ctx, "ARTIST"),
+            album_artist: get_tag(&input_ctx, "ALBUM_ARTIST"),
             album: get_tag(&input_ctx, "ALBUM"),
             title: get_tag(&input_ctx, "TITLE"),
             track: get_tag(&input_ctx, "TRACK"),
+            disc: get_tag(&input_ctx, "DISC"),
+            has_cover_art,
+            stream,
+            root: None,
+            integrity: None,
         });
     }
 
-    pub fn from_directory(path: &PathBuf) -> Result<Vec<Self>, AudioFileError> {
-        if !path.is_dir() {
-            return Err(AudioFileError::NotADirectory);
-        }
-
-        let mut files: Vec<Self> = Vec::new();
-
-        for file in path.read_dir().unwrap() {
-            if let Ok(file) = file {
-                let audio_file = match AudioFile::new(file.path()) {
-                    Ok(af) => af,
-                    Err(AudioFileError::NotAnAudioFile) => continue,
-                    Err(_) => panic!("hdwgh?"), // FIX: real error, happens on hidden folders?
-                };
-                files.push(audio_file);
-            }
-        }
-
-        files.sort_unstable_by_key(|f| f.track.as_deref().and_then(parse_track_string)); // TODO: this adds like 4 seconds on a 1.7k file load
-
-        return Ok(files);
+    pub fn track_number(&self) -> Option<u32> {
+        self.track.as_deref().and_then(parse_track_string)
     }
 
-    pub fn ff_get_album_art(&self) -> Result<Option<Vec<u8>>, ffmpeg_next::Error> {
-        let mut input_ctx = format::input(&self.path)?;
-
-        let stream = match input_ctx.streams().find(|s| {
-            s.parameters().medium() == ffmpeg_next::media::Type::Video
-                && s.disposition()
-                    .contains(ffmpeg_next::format::stream::Disposition::ATTACHED_PIC)
-        }) {
-            Some(s) => s,
-            None => return Ok(None),
-        };
-
-        let stream_index = stream.index();
-
-        for (s, packet) in input_ctx.packets() {
-            if s.index() == stream_index {
-                return Ok(Some(packet.data().unwrap().to_vec()));
-            }
-        }
+    /// Missing any of the tags output files are grouped and named by
+    pub fn is_missing_tags(&self) -> bool {
+        self.artist.is_none() || self.album.is_none() || self.title.is_none()
+    }
 
-        Ok(None)
+    pub fn disc_number(&self) -> Option<u32> {
+        self.disc.as_deref().and_then(parse_track_string)
     }
 
     pub fn load_album_art(
         &self,
         size: Option<u32>,
+        cache: &Arc<CoverArtCache>,
     ) -> mpsc::Receiver<Result<egui::ColorImage, AlbumArtError>> {
         let (tx, rx) = mpsc::channel();
         let path = self.path.clone();
+        let cache = cache.clone();
 
         thread::spawn(move || {
-            let audio_file = AudioFile {
-                path,
-                ..Default::default()
-            };
-            let result = audio_file
-                .ff_get_album_art()
-                .ok()
-                .flatten()
-                .ok_or(AlbumArtError::NotFound);
-
-            let decoded = match decode_thumbnail(&result.unwrap(), size) {
-                Ok(image) => image,
-                Err(_) => return Err(AlbumArtError::DecodeFailed),
-            };
-            let image = egui::ColorImage::from_rgba_unmultiplied(
-                [decoded.width() as usize, decoded.height() as usize],
-                &decoded,
-            );
-
-            let _ = tx.send(Ok(image));
-
-            Ok(())
+            let _ = tx.send(cache.thumbnail(&path, size));
         });
 
         rx
@@ -184,35 +280,60 @@ fn get_tag(ctx: &format::context::Input, key: &str) -> Option<String> {
     None
 }
 
-pub fn decode_thumbnail(
-    bytes: &[u8],
-    size: Option<u32>,
-) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, image::ImageError> {
-    use image::ImageReader;
-    use std::io::Cursor;
+fn parse_track_string(s: &str) -> Option<u32> {
+    s.split('/').next().and_then(|n| n.parse::<u32>().ok())
+}
+
+pub fn format_sample_rate(sample_rate: u32) -> String {
+    format!("{} kHz", sample_rate as f64 / 1000.0)
+}
 
-    let img = {
-        let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
-        let decoded = reader.decode()?;
+pub fn format_channels(channels: u32) -> String {
+    match channels {
+        1 => "Mono".to_string(),
+        2 => "Stereo".to_string(),
+        n => format!("{n} channels"),
+    }
+}
 
-        match size {
-            Some(s) => decoded.thumbnail_exact(s, s).to_rgba8(),
-            None => decoded.to_rgba8(),
-        }
-    };
+pub fn format_bit_rate(bit_rate: u64) -> String {
+    format!("{} kbps", bit_rate / 1000)
+}
+
+pub fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{hours}:{:02}:{:02}", seconds / 60 % 60, seconds % 60),
+    }
+}
 
-    Ok(img)
+pub fn format_file_size(bytes: u64) -> String {
+    match bytes {
+        0..1_000_000 => format!("{:.1} KB", bytes as f64 / 1_000.0),
+        1_000_000..1_000_000_000 => format!("{:.1} MB", bytes as f64 / 1_000_000.0),
+        _ => format!("{:.2} GB", bytes as f64 / 1_000_000_000.0),
+    }
 }
 
-// pub fn get_image_hash(bytes: &[u8]) -> u64 {
-//     use std::collections::hash_map::DefaultHasher;
-//     use std::hash::{Hash, Hasher};
-//
-//     let mut hasher = DefaultHasher::new();
-//     bytes.hash(&mut hasher);
-//     hasher.finish()
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_info_is_readable() {
+        assert_eq!(format_sample_rate(44100), "44.1 kHz");
+        assert_eq!(format_sample_rate(48000), "48 kHz");
+        assert_eq!(format_channels(2), "Stereo");
+        assert_eq!(format_channels(6), "6 channels");
+        assert_eq!(format_bit_rate(320_000), "320 kbps");
+        assert_eq!(format_file_size(25_400_000), "25.4 MB");
+    }
 
-fn parse_track_string(s: &str) -> Option<u32> {
-    s.split('/').next().and_then(|n| n.parse::<u32>().ok())
+    #[test]
+    fn durations_only_show_hours_when_needed() {
+        assert_eq!(format_duration(59.6), "1:00");
+        assert_eq!(format_duration(245.0), "4:05");
+        assert_eq!(format_duration(3725.0), "1:02:05");
+    }
 }
@@ -0,0 +1,121 @@
+use crate::models::audio_file::AudioFile;
+
+#[derive(Default, Clone, PartialEq)]
+pub struct FileFilter {
+    pub text: String,
+    pub codec: Option<String>,
+    pub sample_rate_above: Option<u32>,
+    pub missing_cover: bool,
+    pub missing_tags: bool,
+}
+
+impl FileFilter {
+    pub fn is_active(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// Indices of the files matching the filter, in table order
+    pub fn visible_rows(&self, files: &[AudioFile]) -> Vec<usize> {
+        if !self.is_active() {
+            return (0..files.len()).collect();
+        }
+
+        let words: Vec<String> = self
+            .text
+            .split_whitespace()
+            .map(|w| w.to_lowercase())
+            .collect();
+
+        files
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| self.matches(f, &words))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Every word has to appear in one of the tags or the path, in any order
+    fn matches(&self, file: &AudioFile, words: &[String]) -> bool {
+        if self.codec.as_ref().is_some_and(|c| *c != file.stream.codec)
+            || self
+                .sample_rate_above
+                .is_some_and(|r| file.stream.sample_rate <= r)
+            || (self.missing_cover && file.has_cover_art)
+            || (self.missing_tags && !file.is_missing_tags())
+        {
+            return false;
+        }
+
+        if words.is_empty() {
+            return true;
+        }
+
+        let haystack = [
+            file.artist.as_deref(),
+            file.album_artist.as_deref(),
+            file.album.as_deref(),
+            file.title.as_deref(),
+            file.path.to_str(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("\n")
+        .to_lowercase();
+
+        words.iter().all(|w| haystack.contains(w.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn file(path: &str, artist: &str, codec: &str, has_cover_art: bool) -> AudioFile {
+        let mut file = AudioFile {
+            path: PathBuf::from(path),
+            artist: Some(artist.to_string()),
+            has_cover_art,
+            ..Default::default()
+        };
+        file.stream.codec = codec.to_string();
+        file
+    }
+
+    fn files() -> Vec<AudioFile> {
+        vec![
+            file("/music/Live/01.flac", "Björk", "flac", true),
+            file("/music/Studio/01.mp3", "Bjork", "mp3", false),
+            file("/music/Live/02.mp3", "Other", "mp3", true),
+        ]
+    }
+
+    #[test]
+    fn every_word_has_to_match_a_tag_or_the_path() {
+        let filter = FileFilter {
+            text: "live BJÖRK".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(filter.visible_rows(&files()), vec![0]);
+
+        let filter = FileFilter {
+            text: "live".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(filter.visible_rows(&files()), vec![0, 2]);
+    }
+
+    #[test]
+    fn criteria_narrow_the_text_search() {
+        let filter = FileFilter {
+            codec: Some("mp3".to_string()),
+            missing_cover: true,
+            ..Default::default()
+        };
+        assert_eq!(filter.visible_rows(&files()), vec![1]);
+
+        assert!(!FileFilter::default().is_active());
+        assert_eq!(FileFilter::default().visible_rows(&files()), vec![0, 1, 2]);
+    }
+}
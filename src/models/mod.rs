@@ -1,3 +1,5 @@
 pub mod audio_file;
+pub mod file_filter;
+pub mod preset;
 pub mod settings;
 pub mod task;
@@ -0,0 +1,237 @@
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use crate::models::audio_file::{AudioCodec, AudioContainer, AudioSampleRate};
+use crate::models::settings::{CoverArtFormat, OutputGrouping, Settings, SourceAction};
+
+/// A named copy of the output settings, everything except the output directory so one preset can
+/// be used for several destinations. Presets exported by an older version still import, the
+/// options they don't have take their default
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+#[serde(default)]
+pub struct Preset {
+    pub name: String,
+    pub codec: AudioCodec,
+    pub container: AudioContainer,
+    pub sample_rate: AudioSampleRate,
+    pub bitrate: usize,
+    pub grouping: OutputGrouping,
+    pub embed_art: bool,
+    pub sidecar_art: bool,
+    pub enable_cover_art_resize: bool,
+    pub cover_art_resolution: u32,
+    pub cover_art_only_downscale: bool,
+    pub cover_art_keep_aspect_ratio: bool,
+    pub cover_art_format: CoverArtFormat,
+    pub cover_art_jpeg_quality: u8,
+    pub enable_cover_art_max_size: bool,
+    pub cover_art_max_size_kb: usize,
+    pub source_action: SourceAction,
+    pub source_folder: String,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self::from_settings("", &Settings::default())
+    }
+}
+
+impl Preset {
+    pub fn from_settings(name: &str, settings: &Settings) -> Self {
+        Self {
+            name: name.to_string(),
+            codec: settings.out_codec.clone(),
+            container: settings.out_container.clone(),
+            sample_rate: settings.out_sample_rate.clone(),
+            bitrate: settings.out_bitrate,
+            grouping: settings.out_grouping.clone(),
+            embed_art: settings.out_embed_art,
+            sidecar_art: settings.out_sidecar_art,
+            enable_cover_art_resize: settings.out_enable_cover_art_resize,
+            cover_art_resolution: settings.out_cover_art_resolution,
+            cover_art_only_downscale: settings.out_cover_art_only_downscale,
+            cover_art_keep_aspect_ratio: settings.out_cover_art_keep_aspect_ratio,
+            cover_art_format: settings.out_cover_art_format.clone(),
+            cover_art_jpeg_quality: settings.out_cover_art_jpeg_quality,
+            enable_cover_art_max_size: settings.out_enable_cover_art_max_size,
+            cover_art_max_size_kb: settings.out_cover_art_max_size_kb,
+            source_action: settings.out_source_action.clone(),
+            source_folder: settings.out_source_folder.clone(),
+        }
+    }
+
+    pub fn apply(&self, settings: &mut Settings) {
+        settings.out_codec = self.codec.clone();
+        settings.out_container = self.container.clone();
+        settings.out_sample_rate = self.sample_rate.clone();
+        settings.out_bitrate = self.bitrate;
+        settings.out_grouping = self.grouping.clone();
+        settings.out_embed_art = self.embed_art;
+        settings.out_sidecar_art = self.sidecar_art;
+        settings.out_enable_cover_art_resize = self.enable_cover_art_resize;
+        settings.out_cover_art_resolution = self.cover_art_resolution;
+        settings.out_cover_art_only_downscale = self.cover_art_only_downscale;
+        settings.out_cover_art_keep_aspect_ratio = self.cover_art_keep_aspect_ratio;
+        settings.out_cover_art_format = self.cover_art_format.clone();
+        settings.out_cover_art_jpeg_quality = self.cover_art_jpeg_quality;
+        settings.out_enable_cover_art_max_size = self.enable_cover_art_max_size;
+        settings.out_cover_art_max_size_kb = self.cover_art_max_size_kb;
+        settings.out_source_action = self.source_action.clone();
+        settings.out_source_folder = self.source_folder.clone();
+    }
+
+    /// Whether the settings' output options are exactly this preset's
+    pub fn matches(&self, settings: &Settings) -> bool {
+        *self == Self::from_settings(&self.name, settings)
+    }
+
+    pub fn builtins() -> Vec<Preset> {
+        let base = Preset {
+            name: String::new(),
+            codec: AudioCodec::OPUS,
+            container: AudioContainer::OGG,
+            sample_rate: AudioSampleRate::Studio48,
+            bitrate: 128000,
+            grouping: OutputGrouping::ArtistAlbum,
+            embed_art: true,
+            sidecar_art: false,
+            enable_cover_art_resize: false,
+            cover_art_resolution: 1000,
+            cover_art_only_downscale: true,
+            cover_art_keep_aspect_ratio: true,
+            cover_art_format: CoverArtFormat::Keep,
+            cover_art_jpeg_quality: 90,
+            enable_cover_art_max_size: false,
+            cover_art_max_size_kb: 500,
+            source_action: SourceAction::Keep,
+            source_folder: String::new(),
+        };
+
+        vec![
+            Preset {
+                name: "Phone Opus 128k".to_string(),
+                enable_cover_art_resize: true,
+                cover_art_resolution: 500,
+                cover_art_format: CoverArtFormat::JPEG,
+                ..base.clone()
+            },
+            Preset {
+                name: "Archive FLAC".to_string(),
+                codec: AudioCodec::FLAC,
+                container: AudioContainer::FLAC,
+                sample_rate: AudioSampleRate::CD44,
+                sidecar_art: true,
+                ..base.clone()
+            },
+            Preset {
+                name: "Car MP3 320".to_string(),
+                codec: AudioCodec::MP3,
+                container: AudioContainer::MP3,
+                sample_rate: AudioSampleRate::CD44,
+                bitrate: 320000,
+                enable_cover_art_resize: true,
+                cover_art_resolution: 500,
+                cover_art_format: CoverArtFormat::JPEG,
+                ..base
+            },
+        ]
+    }
+
+    /// Built-in presets followed by the user's
+    pub fn all(settings: &Settings) -> Vec<Preset> {
+        let mut presets = Self::builtins();
+        presets.extend(settings.presets.iter().cloned());
+        presets
+    }
+
+    /// The preset the output settings are exactly, if any
+    pub fn matching(settings: &Settings) -> Option<Preset> {
+        Self::all(settings)
+            .into_iter()
+            .find(|p| p.matches(settings))
+    }
+
+    pub fn find(name: &str, settings: &Settings) -> Option<Preset> {
+        Self::all(settings).into_iter().find(|p| p.name == name)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ron::Error> {
+        let file = fs::File::open(path)?;
+        Ok(ron::de::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ron::Error> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        ron::Options::default().to_io_writer_pretty(&mut writer, self, Default::default())?;
+        writer.into_inner().map_err(|e| e.into_error())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn matches_the_settings_it_was_made_from() {
+        let settings = Settings::default();
+        let preset = Preset::from_settings("Mine", &settings);
+        assert!(preset.matches(&settings));
+
+        // the output directory isn't part of a preset
+        let elsewhere = Settings {
+            out_directory: "elsewhere".to_string(),
+            ..settings.clone()
+        };
+        assert!(preset.matches(&elsewhere));
+
+        let recoded = Settings {
+            out_codec: AudioCodec::MP3,
+            ..settings.clone()
+        };
+        assert!(!preset.matches(&recoded));
+
+        let moving = Settings {
+            out_source_action: SourceAction::Trash,
+            ..settings
+        };
+        assert!(!preset.matches(&moving));
+    }
+
+    #[test]
+    fn applied_preset_matches() {
+        let mut settings = Settings::default();
+        for preset in Preset::builtins() {
+            preset.apply(&mut settings);
+            assert!(preset.matches(&settings));
+            assert_eq!(
+                Preset::matching(&settings).map(|p| p.name),
+                Some(preset.name)
+            );
+        }
+    }
+
+    #[test]
+    fn saved_presets_load_again() {
+        let temp = TempDir::new("preset-saved");
+        let path = temp.path().join("mine.ron");
+        let preset = Preset::from_settings("Mine", &Settings::default());
+        preset.save(&path).unwrap();
+
+        let loaded = Preset::load(&path).unwrap();
+        assert_eq!(loaded.name, "Mine");
+        assert!(loaded.matches(&Settings::default()));
+    }
+
+    #[test]
+    fn older_presets_still_load() {
+        let preset: Preset = ron::from_str("(name: \"Old\", bitrate: 96000)").unwrap();
+
+        assert_eq!(preset.name, "Old");
+        assert_eq!(preset.bitrate, 96000);
+        assert_eq!(preset.source_action, SourceAction::Keep);
+    }
+}
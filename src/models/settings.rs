@@ -1,4 +1,5 @@
 use crate::models::audio_file::{AudioCodec, AudioContainer, AudioSampleRate};
+use crate::models::preset::Preset;
 
 #[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
 pub enum AppTheme {
@@ -16,11 +17,176 @@ pub enum OutputGrouping {
     Artist,
 }
 
+/// What happens to a source once it has been converted to every target and the outputs verified
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Clone, Debug, Default)]
+pub enum SourceAction {
+    #[default]
+    Keep,
+    /// Keeps the path relative to the folder the file was imported from
+    MoveToFolder,
+    Trash,
+    Delete,
+}
+
+impl SourceAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SourceAction::Keep => "Keep",
+            SourceAction::MoveToFolder => "Move to folder",
+            SourceAction::Trash => "Move to trash",
+            SourceAction::Delete => "Delete",
+        }
+    }
+}
+
+/// What happens when the decoder rejects part of a source, the rejected audio is left out of the
+/// outputs
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Clone, Debug, Default)]
+pub enum DecodeErrorPolicy {
+    /// Fails the file on the first error
+    #[default]
+    Abort,
+    Skip,
+    /// Fails the file once more than `run_max_decode_errors` have been skipped
+    SkipUpTo,
+}
+
+impl DecodeErrorPolicy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DecodeErrorPolicy::Abort => "Fail the file",
+            DecodeErrorPolicy::Skip => "Skip bad packets",
+            DecodeErrorPolicy::SkipUpTo => "Skip up to",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Hash, Clone, Debug)]
+pub enum CoverArtFormat {
+    Keep,
+    JPEG,
+    PNG,
+    WEBP,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+pub enum FileView {
+    Tracks,
+    Albums,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum FileColumn {
+    Track,
+    Disc,
+    Artist,
+    AlbumArtist,
+    Album,
+    Title,
+    Path,
+    Codec,
+    SampleRate,
+    BitDepth,
+    Channels,
+    Bitrate,
+    Duration,
+    FileSize,
+    Integrity,
+}
+
+impl FileColumn {
+    pub const ALL: [FileColumn; 15] = [
+        FileColumn::Track,
+        FileColumn::Disc,
+        FileColumn::Artist,
+        FileColumn::AlbumArtist,
+        FileColumn::Album,
+        FileColumn::Title,
+        FileColumn::Path,
+        FileColumn::Codec,
+        FileColumn::SampleRate,
+        FileColumn::BitDepth,
+        FileColumn::Channels,
+        FileColumn::Bitrate,
+        FileColumn::Duration,
+        FileColumn::FileSize,
+        FileColumn::Integrity,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FileColumn::Track => "Track #",
+            FileColumn::Disc => "Disc #",
+            FileColumn::Artist => "Artist",
+            FileColumn::AlbumArtist => "Album Artist",
+            FileColumn::Album => "Album",
+            FileColumn::Title => "Song Title",
+            FileColumn::Path => "File Path",
+            FileColumn::Codec => "Codec",
+            FileColumn::SampleRate => "Sample Rate",
+            FileColumn::BitDepth => "Bit Depth",
+            FileColumn::Channels => "Channels",
+            FileColumn::Bitrate => "Bitrate",
+            FileColumn::Duration => "Duration",
+            FileColumn::FileSize => "File Size",
+            FileColumn::Integrity => "Integrity",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone, Copy, Debug)]
+pub struct SortKey {
+    pub column: FileColumn,
+    pub ascending: bool,
+}
+
+impl SortKey {
+    /// Album artist, album, disc and track, the order files are listed in on an album
+    pub fn album_order() -> Vec<SortKey> {
+        [
+            FileColumn::AlbumArtist,
+            FileColumn::Album,
+            FileColumn::Disc,
+            FileColumn::Track,
+        ]
+        .into_iter()
+        .map(|column| SortKey {
+            column,
+            ascending: true,
+        })
+        .collect()
+    }
+}
+
+/// Another destination converted to alongside the others, with its own preset
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Clone)]
+pub struct OutputTarget {
+    pub preset: String,
+    pub out_directory: String,
+}
+
+/// Fields missing from state saved by an older version take their default
 #[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
 pub struct Settings {
     pub app_theme: AppTheme,
 
     pub run_concurrent_task_count: usize,
+    pub run_convert_filtered_only: bool,
+    pub run_skip_up_to_date: bool,
+    pub run_verify_outputs: bool,
+    pub run_decode_errors: DecodeErrorPolicy,
+    pub run_max_decode_errors: usize,
+
+    pub table_view: FileView,
+    pub table_columns: Vec<FileColumn>,
+    pub table_sort: Vec<SortKey>,
+
+    pub import_recursive: bool,
+    pub import_skip_hidden: bool,
+    pub import_probe_unknown: bool,
+    pub import_extra_extensions: String,
+    pub import_excluded_extensions: String,
 
     pub out_codec: AudioCodec,
     pub out_container: AudioContainer,
@@ -29,6 +195,136 @@ pub struct Settings {
     pub out_directory: String,
     pub out_grouping: OutputGrouping,
     pub out_embed_art: bool,
+    pub out_sidecar_art: bool,
     pub out_enable_cover_art_resize: bool,
     pub out_cover_art_resolution: u32,
+    pub out_cover_art_only_downscale: bool,
+    pub out_cover_art_keep_aspect_ratio: bool,
+    pub out_cover_art_format: CoverArtFormat,
+    pub out_cover_art_jpeg_quality: u8,
+    pub out_enable_cover_art_max_size: bool,
+    pub out_cover_art_max_size_kb: usize,
+    pub out_source_action: SourceAction,
+    pub out_source_folder: String,
+    /// Not stored in presets, so turning them on doesn't make converted files out of date
+    pub out_preserve_times: bool,
+    pub out_preserve_permissions: bool,
+
+    pub out_multiple_targets: bool,
+    pub out_targets: Vec<OutputTarget>,
+
+    /// User presets, the built-in ones aren't stored
+    pub presets: Vec<Preset>,
+
+    /// Library mirrored into the output directory by the sync
+    pub sync_source: String,
+    pub sync_delete_removed: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            app_theme: AppTheme::System,
+            run_concurrent_task_count: 2,
+            run_convert_filtered_only: false,
+            run_skip_up_to_date: true,
+            run_verify_outputs: false,
+            run_decode_errors: DecodeErrorPolicy::Abort,
+            run_max_decode_errors: 10,
+            table_view: FileView::Tracks,
+            table_columns: vec![
+                FileColumn::Track,
+                FileColumn::Artist,
+                FileColumn::Album,
+                FileColumn::Title,
+                FileColumn::Path,
+            ],
+            table_sort: SortKey::album_order(),
+            import_recursive: true,
+            import_skip_hidden: true,
+            import_probe_unknown: true,
+            import_extra_extensions: String::new(),
+            import_excluded_extensions: String::new(),
+            out_codec: AudioCodec::OPUS,
+            out_container: AudioContainer::OGG,
+            out_sample_rate: AudioSampleRate::Studio48,
+            out_bitrate: 64000,
+            out_directory: "./".to_string(),
+            out_grouping: OutputGrouping::ArtistAlbum,
+            out_embed_art: true,
+            out_sidecar_art: false,
+            out_enable_cover_art_resize: false,
+            out_cover_art_resolution: 1000,
+            out_cover_art_only_downscale: true,
+            out_cover_art_keep_aspect_ratio: true,
+            out_cover_art_format: CoverArtFormat::Keep,
+            out_cover_art_jpeg_quality: 90,
+            out_enable_cover_art_max_size: false,
+            out_cover_art_max_size_kb: 500,
+            out_source_action: SourceAction::Keep,
+            out_source_folder: String::new(),
+            out_preserve_times: false,
+            out_preserve_permissions: false,
+            out_multiple_targets: false,
+            out_targets: Vec::new(),
+            presets: Vec::new(),
+            sync_source: String::new(),
+            sync_delete_removed: false,
+        }
+    }
+}
+
+impl Settings {
+    /// How many decoding errors are skipped before a file fails, None when there's no limit
+    pub fn max_decode_errors(&self) -> Option<usize> {
+        match self.run_decode_errors {
+            DecodeErrorPolicy::Abort => Some(0),
+            DecodeErrorPolicy::Skip => None,
+            DecodeErrorPolicy::SkipUpTo => Some(self.run_max_decode_errors),
+        }
+    }
+
+    /// The settings to convert each file with, one per output target along with the target's name,
+    /// or just these settings when not converting to multiple targets. A target whose preset has
+    /// since been deleted uses the current output settings
+    pub fn target_settings(&self) -> Vec<(Option<String>, Settings)> {
+        if !self.out_multiple_targets || self.out_targets.is_empty() {
+            return vec![(None, self.clone())];
+        }
+
+        self.out_targets
+            .iter()
+            .map(|target| {
+                let mut settings = self.clone();
+                if let Some(preset) = Preset::find(&target.preset, self) {
+                    preset.apply(&mut settings);
+                }
+                settings.out_directory = target.out_directory.clone();
+                settings.out_multiple_targets = false;
+
+                let name = format!("{} → {}", target.preset, target.out_directory);
+                (Some(name), settings)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_error_limit_follows_the_policy() {
+        let mut settings = Settings {
+            run_max_decode_errors: 3,
+            ..Default::default()
+        };
+        assert_eq!(settings.max_decode_errors(), Some(0));
+
+        settings.run_decode_errors = DecodeErrorPolicy::Skip;
+        assert_eq!(settings.max_decode_errors(), None);
+
+        settings.run_decode_errors = DecodeErrorPolicy::SkipUpTo;
+        assert_eq!(settings.max_decode_errors(), Some(3));
+    }
 }
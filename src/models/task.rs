@@ -1,109 +1,403 @@
-use std::path::PathBuf;
-use std::sync::mpsc;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
 use std::thread;
 
 use crate::app::{NO_ALBUM, NO_ARTIST};
+use crate::attributes;
+use crate::cover_art_cache::CoverArtCache;
+use crate::manifest;
 use crate::models::audio_file::AudioFile;
-use crate::models::settings::{OutputGrouping, Settings};
-use crate::transcode;
+use crate::models::settings::{OutputGrouping, Settings, SourceAction};
+use crate::source_handling;
+use crate::transcode::{self, OutputOptions};
+use crate::verify;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug)]
 enum TaskStatus {
     Started,
     Paused,
-    Failed,
-    Completed,
+    Completed {
+        /// The result of each target, failed targets carry the reason
+        results: Vec<Result<(), String>>,
+        /// The source's hash and metadata from before converting, for the manifest. None when no
+        /// manifest is kept, hashing reads the whole source a second time
+        source: Option<(u64, fs::Metadata)>,
+        /// Why the source was kept, or couldn't be moved or deleted
+        source_error: Option<String>,
+        /// Decoding errors skipped over, the outputs are missing the audio they affected
+        concealed_errors: usize,
+    },
 }
 
-#[derive(Debug)]
 pub struct Task {
     pub file: AudioFile,
+    /// Settings for each output, named when converting to several targets
+    pub targets: Vec<(Option<String>, Settings)>,
+    /// Set when mirroring a library, the file keeps its path relative to the root in the output
+    /// directory instead of being grouped
+    pub source_root: Option<PathBuf>,
     status: Option<mpsc::Receiver<TaskStatus>>,
+    results: Option<Vec<Result<(), String>>>,
+    source: Option<(u64, fs::Metadata)>,
+    source_error: Option<String>,
+    concealed_errors: usize,
+}
+
+/// Gives the output the source's times and permissions when the options ask for it
+fn copy_attributes(
+    source: Option<&fs::Metadata>,
+    output: &Path,
+    options: &OutputOptions,
+) -> Result<(), String> {
+    let Some(source) = source else {
+        return Ok(());
+    };
+
+    if options.preserve_times {
+        attributes::copy_times(source, output)
+            .map_err(|e| format!("Couldn't copy the source's times: {e}"))?;
+    }
+    if options.preserve_permissions {
+        attributes::copy_permissions(source, output)
+            .map_err(|e| format!("Couldn't copy the source's permissions: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// The folder the file is written to, according to the output grouping
+pub fn output_directory(file: &AudioFile, settings: &Settings) -> PathBuf {
+    match settings.out_grouping {
+        OutputGrouping::NoGrouping => PathBuf::from(&settings.out_directory),
+        OutputGrouping::Copy => {
+            let mut out = PathBuf::from(&settings.out_directory);
+            if let Some(parent) = file.path.parent() {
+                if let Some(direct_parent) = parent.file_name().and_then(|f| f.to_str()) {
+                    out.push(&direct_parent);
+                };
+            }
+
+            out
+        }
+        OutputGrouping::ArtistAlbum => {
+            let mut out = PathBuf::from(&settings.out_directory);
+            let directory = format!(
+                "{} - {}",
+                file.artist.as_deref().unwrap_or(NO_ARTIST),
+                file.album.as_deref().unwrap_or(NO_ALBUM)
+            );
+            out.push(directory);
+            out
+        }
+        OutputGrouping::Album => {
+            let mut out = PathBuf::from(&settings.out_directory);
+            out.push(file.album.as_deref().unwrap_or(NO_ALBUM));
+            out
+        }
+        OutputGrouping::Artist => {
+            let mut out = PathBuf::from(&settings.out_directory);
+            out.push(file.artist.as_deref().unwrap_or(NO_ARTIST));
+            out
+        }
+    }
+}
+
+/// The folder the file is written to, keeping its folder relative to `source_root` when mirroring
+/// a library instead of being grouped
+pub fn target_directory(
+    file: &AudioFile,
+    settings: &Settings,
+    source_root: Option<&Path>,
+) -> PathBuf {
+    let Some(root) = source_root else {
+        return output_directory(file, settings);
+    };
+
+    let mut out = PathBuf::from(&settings.out_directory);
+    if let Some(relative) = file.path.parent().and_then(|p| p.strip_prefix(root).ok()) {
+        out.push(relative);
+    }
+
+    out
+}
+
+pub fn output_path(file: &AudioFile, settings: &Settings, source_root: Option<&Path>) -> PathBuf {
+    OutputOptions::from_settings(settings, target_directory(file, settings, source_root))
+        .output_path(file)
+}
+
+/// Files that more than one target would write, with the names of those targets. Targets sharing
+/// an output directory and a container would overwrite each other's outputs
+pub fn colliding_outputs(files: &[AudioFile], settings: &Settings) -> Vec<(PathBuf, Vec<String>)> {
+    let targets = settings.target_settings();
+    if targets.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut writers: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for file in files {
+        for (i, (_, settings)) in targets.iter().enumerate() {
+            let targets_writing = writers
+                .entry(output_path(file, settings, None))
+                .or_default();
+            if !targets_writing.contains(&i) {
+                targets_writing.push(i);
+            }
+        }
+    }
+
+    let mut collisions: Vec<(PathBuf, Vec<String>)> = writers
+        .into_iter()
+        .filter(|(_, targets_writing)| targets_writing.len() > 1)
+        .map(|(path, targets_writing)| {
+            let names = targets_writing
+                .into_iter()
+                .map(|i| targets[i].0.clone().unwrap_or_default())
+                .collect();
+            (path, names)
+        })
+        .collect();
+    collisions.sort();
+
+    collisions
 }
 
 impl Task {
-    pub fn new(file: AudioFile) -> Self {
-        return Task { file, status: None };
+    pub fn new(file: AudioFile, targets: Vec<(Option<String>, Settings)>) -> Self {
+        return Task {
+            file,
+            targets,
+            source_root: None,
+            status: None,
+            results: None,
+            source: None,
+            source_error: None,
+            concealed_errors: 0,
+        };
+    }
+
+    /// Converts a file of a mirrored library into the same folder structure
+    pub fn mirrored(file: AudioFile, settings: Settings, source_root: PathBuf) -> Self {
+        Task {
+            source_root: Some(source_root),
+            ..Task::new(file, vec![(None, settings)])
+        }
     }
 
-    pub fn start_transcode(&mut self, settings: &Settings) {
+    /// The folder the file is written to for a target
+    pub fn target_directory(&self, settings: &Settings) -> PathBuf {
+        target_directory(&self.file, settings, self.source_root.as_deref())
+    }
+
+    pub fn output_path(&self, settings: &Settings) -> PathBuf {
+        output_path(&self.file, settings, self.source_root.as_deref())
+    }
+
+    /// What to do with the source, from the first target that doesn't keep it
+    fn source_action(&self) -> (SourceAction, String) {
+        self.targets
+            .iter()
+            .map(|(_, settings)| settings)
+            .find(|settings| settings.out_source_action != SourceAction::Keep)
+            .map(|settings| {
+                (
+                    settings.out_source_action.clone(),
+                    settings.out_source_folder.clone(),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether the file is recorded in the output directory's manifest, which is only read to skip
+    /// files that are up to date and to find what a sync has to remove
+    fn keeps_manifest(&self) -> bool {
+        self.source_root.is_some()
+            || self
+                .targets
+                .iter()
+                .any(|(_, settings)| settings.run_skip_up_to_date)
+    }
+
+    /// Converts the file to every target at once, so the source is only read and decoded once
+    pub fn start_transcode(&mut self, cover_art_cache: &Arc<CoverArtCache>) {
         let file = self.file.clone();
-        let settings = settings.clone();
+        let cover_art_cache = cover_art_cache.clone();
         let (tx, rx) = mpsc::channel();
 
         let _ = tx.send(TaskStatus::Started);
         self.status = Some(rx);
 
-        let out_dir: PathBuf = match settings.out_grouping {
-            OutputGrouping::NoGrouping => PathBuf::from(settings.out_directory),
-            OutputGrouping::Copy => {
-                let mut out = PathBuf::from(&settings.out_directory);
-                if let Some(parent) = self.file.path.parent() {
-                    if let Some(direct_parent) = parent.file_name().and_then(|f| f.to_str()) {
-                        out.push(&direct_parent);
-                    };
-                }
+        let outputs: Vec<OutputOptions> = self
+            .targets
+            .iter()
+            .map(|(_, settings)| {
+                let out_dir = self.target_directory(settings);
 
-                out
-            }
-            OutputGrouping::ArtistAlbum => {
-                let mut out = PathBuf::from(settings.out_directory);
-                let directory = format!(
-                    "{} - {}",
-                    self.file.artist.as_deref().unwrap_or(NO_ARTIST),
-                    self.file.album.as_deref().unwrap_or(NO_ALBUM)
-                );
-                out.push(directory);
-                out
-            }
-            OutputGrouping::Album => {
-                let mut out = PathBuf::from(settings.out_directory);
-                out.push(self.file.album.as_deref().unwrap_or(NO_ALBUM));
-                out
-            }
-            OutputGrouping::Artist => {
-                let mut out = PathBuf::from(settings.out_directory);
-                out.push(self.file.artist.as_deref().unwrap_or(NO_ARTIST));
-                out
-            }
-        };
+                let _ = fs::create_dir_all(&out_dir);
+
+                let mut options = OutputOptions::from_settings(settings, out_dir);
+                // without grouping every album shares the folder, one cover would be wrong for most
+                options.sidecar_cover_art &= self.source_root.is_some()
+                    || settings.out_grouping != OutputGrouping::NoGrouping;
+                options
+            })
+            .collect();
 
-        use std::fs;
-        let _ = fs::create_dir(&out_dir);
+        let output_paths: Vec<PathBuf> = outputs.iter().map(|o| o.output_path(&file)).collect();
+        let (source_action, source_folder) = self.source_action();
+        let max_errors = self
+            .targets
+            .first()
+            .and_then(|(_, settings)| settings.max_decode_errors());
+        // the source is only moved or deleted once every output has been decoded and checked
+        let handles_source = source_action != SourceAction::Keep;
+        let hash_source = self.keeps_manifest();
 
         thread::spawn(move || {
-            match transcode::convert_file(
-                file,
-                &settings.out_codec,
-                &settings.out_sample_rate,
-                settings.out_bitrate,
-                &out_dir,
-                &settings.out_container,
-                settings.out_embed_art,
-                settings.out_enable_cover_art_resize,
-                settings.out_cover_art_resolution,
-            ) {
-                Ok(_) => {
-                    let _ = tx.send(TaskStatus::Completed);
-                }
-                Err(_) => {
-                    let _ = tx.send(TaskStatus::Failed);
-                }
-            }
+            // taken first, as reading the source to hash it can update its access time
+            let metadata = fs::metadata(&file.path).ok();
+            // hashed before converting so a source changed meanwhile is converted again next time
+            let source = match hash_source {
+                true => manifest::hash_file(&file.path).ok().zip(metadata.clone()),
+                false => None,
+            };
+
+            let converted = transcode::convert_file(&file, &outputs, &cover_art_cache, max_errors);
+            let source_duration = converted.source_duration;
+            let results: Vec<Result<(), String>> = converted
+                .results
+                .into_iter()
+                .zip(converted.embedded_cover_art)
+                .zip(&outputs)
+                .zip(&output_paths)
+                .map(|(((result, embedded_cover_art), options), path)| {
+                    let result = match result {
+                        Ok(()) if options.verify || handles_source => {
+                            // a damaged output would otherwise be taken for a converted one
+                            verify::verify_output(path, &file, source_duration, embedded_cover_art)
+                                .inspect_err(|_| {
+                                    let _ = fs::remove_file(path);
+                                })
+                        }
+                        result => result.map_err(|e| e.to_string()),
+                    };
+                    result.and_then(|()| copy_attributes(metadata.as_ref(), path, options))
+                })
+                .collect();
+
+            // the source is only touched once every target has been converted and verified
+            let source_error = if !handles_source || results.iter().any(|r| r.is_err()) {
+                None
+            } else if converted.concealed_errors > 0 {
+                // the outputs are missing audio, the source may be the only complete copy
+                Some(format!(
+                    "Kept the source, {} decoding error(s) were skipped",
+                    converted.concealed_errors
+                ))
+            } else {
+                source_handling::handle_source(&file, &source_action, &source_folder)
+                    .err()
+                    .map(|e| format!("{} failed: {e}", source_action.label()))
+            };
+
+            let _ = tx.send(TaskStatus::Completed {
+                results,
+                source,
+                source_error,
+                concealed_errors: converted.concealed_errors,
+            });
         });
     }
 
-    pub fn is_complete(&self) -> bool {
+    pub fn is_complete(&mut self) -> bool {
+        if self.results.is_some() {
+            return true;
+        }
         let Some(rx) = &self.status else {
             return false;
         };
 
-        match rx.try_recv() {
-            Ok(TaskStatus::Completed) | Ok(TaskStatus::Failed) => true,
-            Ok(TaskStatus::Paused) => false,
-            Ok(TaskStatus::Started) => false,
-            Err(mpsc::TryRecvError::Empty) => false,
-            Err(mpsc::TryRecvError::Disconnected) => true,
+        let results = match rx.try_recv() {
+            Ok(TaskStatus::Completed {
+                results,
+                source,
+                source_error,
+                concealed_errors,
+            }) => {
+                self.source = source;
+                self.source_error = source_error;
+                self.concealed_errors = concealed_errors;
+                results
+            }
+            Ok(TaskStatus::Paused) => return false,
+            Ok(TaskStatus::Started) => return false,
+            Err(mpsc::TryRecvError::Empty) => return false,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                vec![Err("Conversion stopped unexpectedly".to_string()); self.targets.len()]
+            }
+        };
+
+        self.results = Some(results);
+        true
+    }
+
+    /// The outcome of each target once `is_complete` returns true
+    pub fn results(&self) -> &[Result<(), String>] {
+        self.results.as_deref().unwrap_or_default()
+    }
+
+    /// The source's hash and metadata taken before converting, when the file is recorded in the
+    /// manifest
+    pub fn source(&self) -> Option<&(u64, fs::Metadata)> {
+        self.source.as_ref()
+    }
+
+    pub fn source_error(&self) -> Option<&str> {
+        self.source_error.as_deref()
+    }
+
+    pub fn concealed_errors(&self) -> usize {
+        self.concealed_errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::settings::OutputTarget;
+
+    fn targets(targets: &[(&str, &str)]) -> Settings {
+        Settings {
+            out_multiple_targets: true,
+            out_targets: targets
+                .iter()
+                .map(|(preset, directory)| OutputTarget {
+                    preset: preset.to_string(),
+                    out_directory: directory.to_string(),
+                })
+                .collect(),
+            ..Settings::default()
         }
     }
+
+    #[test]
+    fn targets_writing_the_same_file_collide() {
+        let files = vec![AudioFile {
+            path: PathBuf::from("/music/a.flac"),
+            ..Default::default()
+        }];
+
+        let shared = targets(&[("Phone Opus 128k", "/out"), ("Phone Opus 128k", "/out")]);
+        let collisions = colliding_outputs(&files, &shared);
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].1.len(), 2);
+
+        let other_container = targets(&[("Phone Opus 128k", "/out"), ("Car MP3 320", "/out")]);
+        assert!(colliding_outputs(&files, &other_container).is_empty());
+
+        let other_directory = targets(&[("Phone Opus 128k", "/out"), ("Phone Opus 128k", "/car")]);
+        assert!(colliding_outputs(&files, &other_directory).is_empty());
+    }
 }
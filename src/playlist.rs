@@ -0,0 +1,457 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::models::settings::Settings;
+
+pub const PLAYLIST_EXTENSIONS: [&str; 3] = ["m3u", "m3u8", "pls"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    M3U,
+    M3U8,
+    PLS,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PlaylistEntry {
+    /// As written in the playlist
+    pub location: String,
+    /// Resolved against the playlist's folder, None for streams, other URLs and Windows paths elsewhere
+    pub path: Option<PathBuf>,
+    pub title: Option<String>,
+    /// Seconds, from `#EXTINF` or `LengthN`
+    pub duration: Option<i64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Playlist {
+    pub path: PathBuf,
+    pub format: PlaylistFormat,
+    /// The folder the playlist was imported from, it keeps its path relative to it in the output
+    pub root: Option<PathBuf>,
+    pub entries: Vec<PlaylistEntry>,
+}
+
+pub fn is_playlist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| PLAYLIST_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Resolves `.` and `..` without touching the file system, the files may not exist
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Decodes the `%XX` escapes of a `file://` URL
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Paths like `C:\Music`, `\\server\share` or `\Music`, and `file:///C:/Music` URLs, which can't be
+/// found on other systems
+#[cfg(not(windows))]
+fn is_windows_absolute(location: &str) -> bool {
+    let is_drive = |b: &[u8]| b.len() >= 2 && b[0].is_ascii_alphabetic() && b[1] == b':';
+    let bytes = location.as_bytes();
+
+    is_drive(bytes)
+        || (bytes.first() == Some(&b'/') && is_drive(&bytes[1..]))
+        || location.starts_with('\\')
+}
+
+/// The entry's path, relative entries are relative to the playlist's folder. None for URLs and,
+/// outside Windows, for Windows absolute paths
+fn resolve(location: &str, directory: &Path) -> Option<PathBuf> {
+    let location = match location.strip_prefix("file://") {
+        Some(url) => percent_decode(url.strip_prefix("localhost").unwrap_or(url)),
+        None if location.contains("://") => return None,
+        None => location.to_string(),
+    };
+
+    // playlists written on Windows use backslashes
+    #[cfg(not(windows))]
+    let location = match is_windows_absolute(&location) {
+        true => return None,
+        false => location.replace('\\', "/"),
+    };
+
+    Some(normalize(&directory.join(location)))
+}
+
+/// The path from `directory` to `path` with forward slashes, as players on every system accept
+/// them. Paths with nothing in common, e.g. on another drive, are kept as they are
+pub fn relative_path(directory: &Path, path: &Path) -> String {
+    let directory: Vec<Component> = normalize(directory).components().collect();
+    let path = normalize(path);
+    let target: Vec<Component> = path.components().collect();
+
+    let common = directory
+        .iter()
+        .zip(&target)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let is_rooted = |c: &Component| matches!(c, Component::Prefix(_) | Component::RootDir);
+    if common == 0
+        && (directory.first().is_some_and(is_rooted) || target.first().is_some_and(is_rooted))
+    {
+        return path.to_string_lossy().to_string();
+    }
+
+    let mut parts: Vec<String> = vec!["..".to_string(); directory.len() - common];
+    parts.extend(
+        target[common..]
+            .iter()
+            .map(|c| c.as_os_str().to_string_lossy().to_string()),
+    );
+
+    parts.join("/")
+}
+
+impl Playlist {
+    pub fn read(path: &Path, root: Option<PathBuf>) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        // .m3u is often Latin-1, which maps byte for byte to the first 256 code points
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => e.into_bytes().into_iter().map(char::from).collect(),
+        };
+        let text = text.trim_start_matches('\u{feff}');
+
+        let format = match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .as_deref()
+        {
+            Some("pls") => PlaylistFormat::PLS,
+            Some("m3u8") => PlaylistFormat::M3U8,
+            _ => PlaylistFormat::M3U,
+        };
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let entries = match format {
+            PlaylistFormat::PLS => parse_pls(text, directory),
+            _ => parse_m3u(text, directory),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            root,
+            entries,
+        })
+    }
+
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Entries that are URLs or whose file doesn't exist
+    pub fn missing_entries(&self) -> Vec<&PlaylistEntry> {
+        self.entries
+            .iter()
+            .filter(|e| e.path.as_ref().is_none_or(|p| !p.is_file()))
+            .collect()
+    }
+
+    /// Where the rewritten playlist is written, keeping its path within the folder it was
+    /// imported from. It's always written as UTF-8, so `.m3u` playlists become `.m3u8` as players
+    /// read `.m3u` as Latin-1
+    pub fn output_path(&self, settings: &Settings) -> PathBuf {
+        let out = PathBuf::from(&settings.out_directory);
+        let path = match self
+            .root
+            .as_ref()
+            .and_then(|root| self.path.strip_prefix(root).ok())
+        {
+            Some(relative) => out.join(relative),
+            None => out.join(self.path.file_name().unwrap_or_default()),
+        };
+
+        match self.format {
+            PlaylistFormat::M3U => path.with_extension("m3u8"),
+            _ => path,
+        }
+    }
+
+    /// Writes the playlist in its own format with the given entries, each with the path of the
+    /// converted file
+    pub fn write(
+        &self,
+        destination: &Path,
+        entries: &[(PathBuf, &PlaylistEntry)],
+    ) -> io::Result<()> {
+        let directory = destination.parent().unwrap_or(Path::new(""));
+        let mut text = String::new();
+
+        match self.format {
+            PlaylistFormat::PLS => {
+                text += "[playlist]\n";
+                for (i, (path, entry)) in entries.iter().enumerate() {
+                    let n = i + 1;
+                    let _ = writeln!(text, "File{n}={}", relative_path(directory, path));
+                    if let Some(title) = &entry.title {
+                        let _ = writeln!(text, "Title{n}={title}");
+                    }
+                    if let Some(duration) = entry.duration {
+                        let _ = writeln!(text, "Length{n}={duration}");
+                    }
+                }
+                let _ = writeln!(text, "NumberOfEntries={}", entries.len());
+                text += "Version=2\n";
+            }
+            PlaylistFormat::M3U | PlaylistFormat::M3U8 => {
+                text += "#EXTM3U\n";
+                for (path, entry) in entries {
+                    if entry.title.is_some() || entry.duration.is_some() {
+                        let _ = writeln!(
+                            text,
+                            "#EXTINF:{},{}",
+                            entry.duration.unwrap_or(-1),
+                            entry.title.as_deref().unwrap_or_default()
+                        );
+                    }
+                    let _ = writeln!(text, "{}", relative_path(directory, path));
+                }
+            }
+        }
+
+        fs::create_dir_all(directory)?;
+        fs::write(destination, text)
+    }
+}
+
+fn parse_m3u(text: &str, directory: &Path) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut info: Option<(Option<i64>, Option<String>)> = None;
+
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:<seconds>[ attributes],<title>
+            let (duration, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            let duration = duration
+                .split_whitespace()
+                .next()
+                .and_then(|d| d.parse::<f64>().ok())
+                .filter(|d| *d >= 0.0)
+                .map(|d| d.round() as i64);
+            let title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+            info = Some((duration, title));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let (duration, title) = info.take().unwrap_or_default();
+        entries.push(PlaylistEntry {
+            location: line.to_string(),
+            path: resolve(line, directory),
+            title,
+            duration,
+        });
+    }
+
+    entries
+}
+
+fn parse_pls(text: &str, directory: &Path) -> Vec<PlaylistEntry> {
+    // entries are numbered and their keys can come in any order
+    let mut entries: Vec<(usize, PlaylistEntry)> = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim().to_string();
+
+        let Some((field, n)) = ["file", "title", "length"].into_iter().find_map(|field| {
+            key.strip_prefix(field)
+                .and_then(|n| n.parse::<usize>().ok())
+                .map(|n| (field, n))
+        }) else {
+            continue;
+        };
+
+        let i = match entries.iter().position(|(i, _)| *i == n) {
+            Some(i) => i,
+            None => {
+                entries.push((n, PlaylistEntry::default()));
+                entries.len() - 1
+            }
+        };
+        let entry = &mut entries[i].1;
+
+        match field {
+            "file" => {
+                entry.path = resolve(&value, directory);
+                entry.location = value;
+            }
+            "title" => entry.title = Some(value).filter(|t| !t.is_empty()),
+            _ => entry.duration = value.parse::<i64>().ok().filter(|d| *d >= 0),
+        }
+    }
+
+    entries.sort_by_key(|(n, _)| *n);
+    entries
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .filter(|entry| !entry.location.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths() {
+        let lists = Path::new("/music/out/lists");
+        assert_eq!(
+            relative_path(lists, Path::new("/music/out/Artist/a.ogg")),
+            "../Artist/a.ogg"
+        );
+        assert_eq!(
+            relative_path(lists, Path::new("/music/out/lists/a.ogg")),
+            "a.ogg"
+        );
+        assert_eq!(
+            relative_path(lists, Path::new("/music/out/lists/./x/../a.ogg")),
+            "a.ogg"
+        );
+        assert_eq!(
+            relative_path(Path::new("out/lists"), Path::new("out/Artist/a.ogg")),
+            "../Artist/a.ogg"
+        );
+        assert_eq!(
+            relative_path(Path::new(""), Path::new("Artist/a.ogg")),
+            "Artist/a.ogg"
+        );
+        // nothing in common with a relative folder
+        assert_eq!(
+            relative_path(Path::new("lists"), Path::new("/music/a.ogg")),
+            "/music/a.ogg"
+        );
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("a%20b.flac"), "a b.flac");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%2"), "%zz%2");
+    }
+
+    #[test]
+    fn m3u_entries() {
+        let text = "#EXTM3U\n\
+            #EXTINF:123,Artist - Title\n\
+            ../Artist/a.flac\n\
+            \n\
+            # a comment\n\
+            file:///music/Other/b%20c.flac\n\
+            http://radio.example/stream\n\
+            #EXTINF:-1 tvg-id=\"x\",\n\
+            ./d.flac\n";
+        let entries = parse_m3u(text, Path::new("/music/lists"));
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!(
+            entries[0].path.as_deref(),
+            Some(Path::new("/music/Artist/a.flac"))
+        );
+        assert_eq!(entries[0].title.as_deref(), Some("Artist - Title"));
+        assert_eq!(entries[0].duration, Some(123));
+
+        assert_eq!(
+            entries[1].path.as_deref(),
+            Some(Path::new("/music/Other/b c.flac"))
+        );
+        // #EXTINF only describes the entry that follows it
+        assert_eq!(entries[1].title, None);
+
+        assert_eq!(entries[2].location, "http://radio.example/stream");
+        assert_eq!(entries[2].path, None);
+
+        assert_eq!(
+            entries[3].path.as_deref(),
+            Some(Path::new("/music/lists/d.flac"))
+        );
+        assert_eq!(entries[3].title, None);
+        assert_eq!(entries[3].duration, None);
+    }
+
+    #[test]
+    fn pls_keys_in_any_order() {
+        let text = "[playlist]\n\
+            Title2=Second\n\
+            File2=b.mp3\n\
+            Length1=60\n\
+            File1=../a.mp3\n\
+            Title3=No file\n\
+            File10=j.mp3\n\
+            NumberOfEntries=3\n\
+            Version=2\n";
+        let entries = parse_pls(text, Path::new("/music/lists"));
+
+        let locations: Vec<&str> = entries.iter().map(|e| e.location.as_str()).collect();
+        assert_eq!(locations, ["../a.mp3", "b.mp3", "j.mp3"]);
+        assert_eq!(entries[0].path.as_deref(), Some(Path::new("/music/a.mp3")));
+        assert_eq!(entries[0].duration, Some(60));
+        assert_eq!(entries[1].title.as_deref(), Some("Second"));
+        assert_eq!(entries[2].title, None);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn windows_paths_elsewhere_are_unresolved() {
+        let directory = Path::new("/music/lists");
+        assert_eq!(resolve("C:\\Music\\a.flac", directory), None);
+        assert_eq!(resolve("\\\\server\\share\\a.flac", directory), None);
+        assert_eq!(resolve("file:///C:/Music/a.flac", directory), None);
+        assert_eq!(
+            resolve("Artist\\a.flac", directory),
+            Some(PathBuf::from("/music/lists/Artist/a.flac"))
+        );
+    }
+}
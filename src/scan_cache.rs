@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::models::audio_file::AudioFile;
+
+/// Bump whenever `AudioFile` gains fields, so stale entries are probed again instead of loading
+/// with missing information
+const SCAN_CACHE_VERSION: u32 = 4;
+const SCAN_CACHE_FILE: &str = "scan_cache.ron";
+
+/// Held while saving, every save writes the same temporary file
+static SAVING: Mutex<()> = Mutex::new(());
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+struct CacheEntry {
+    size: u64,
+    modified: Option<SystemTime>,
+    file: AudioFile,
+}
+
+/// Probed files from previous runs, an entry is only used while the file's size and modification
+/// time are unchanged
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct ScanCache {
+    version: u32,
+    entries: HashMap<PathBuf, CacheEntry>,
+}
+
+impl Default for ScanCache {
+    fn default() -> Self {
+        Self {
+            version: SCAN_CACHE_VERSION,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl ScanCache {
+    fn cache_path() -> Option<PathBuf> {
+        eframe::storage_dir(crate::APP_NAME).map(|dir| dir.join(SCAN_CACHE_FILE))
+    }
+
+    pub fn load() -> Self {
+        let Some(path) = Self::cache_path() else {
+            return Self::default();
+        };
+        let Ok(file) = fs::File::open(path) else {
+            return Self::default();
+        };
+
+        match ron::de::from_reader::<_, Self>(BufReader::new(file)) {
+            Ok(cache) if cache.version == SCAN_CACHE_VERSION => cache,
+            _ => Self::default(),
+        }
+    }
+
+    /// Writes a copy of the entries taken under the lock, so scanners aren't kept waiting while
+    /// it's saved. Entries of files that no longer exist are left out, and the copy is written to a
+    /// temporary file that replaces the cache so it's never left half written. Saves from several
+    /// threads run one at a time, the last one taking the newest copy
+    pub fn save(cache: &Mutex<Self>) -> Result<(), ron::Error> {
+        let Some(path) = Self::cache_path() else {
+            return Ok(());
+        };
+        let _saving = SAVING.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut snapshot = Self {
+            entries: cache.lock().unwrap().entries.clone(),
+            ..Self::default()
+        };
+        snapshot.entries.retain(|source, _| source.exists());
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary = path.with_extension("ron.tmp");
+
+        let mut writer = BufWriter::new(fs::File::create(&temporary)?);
+        ron::Options::default().to_io_writer(&mut writer, &snapshot)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+
+        Ok(fs::rename(temporary, path)?)
+    }
+
+    pub fn get(&self, path: &Path, metadata: &fs::Metadata) -> Option<AudioFile> {
+        let entry = self.entries.get(path)?;
+        if entry.size != metadata.len() || entry.modified != metadata.modified().ok() {
+            return None;
+        }
+
+        Some(entry.file.clone())
+    }
+
+    pub fn insert(&mut self, file: &AudioFile, metadata: &fs::Metadata) {
+        self.entries.insert(
+            file.path.clone(),
+            CacheEntry {
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+                file: file.clone(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn changed_files_are_probed_again() {
+        let temp = TempDir::new("scan-cache");
+        let path = temp.path().join("a.flac");
+        fs::write(&path, b"audio").unwrap();
+
+        let file = AudioFile {
+            path: path.clone(),
+            title: Some("A".to_string()),
+            ..Default::default()
+        };
+        let mut cache = ScanCache::default();
+        cache.insert(&file, &fs::metadata(&path).unwrap());
+
+        let cached = cache.get(&path, &fs::metadata(&path).unwrap());
+        assert_eq!(cached.and_then(|f| f.title).as_deref(), Some("A"));
+
+        fs::write(&path, b"longer audio").unwrap();
+        assert!(cache.get(&path, &fs::metadata(&path).unwrap()).is_none());
+        assert!(
+            cache
+                .get(&temp.path().join("b.flac"), &fs::metadata(&path).unwrap())
+                .is_none()
+        );
+    }
+}
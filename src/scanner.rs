@@ -0,0 +1,339 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+
+use crate::models::audio_file::{
+    AUDIO_EXTENSIONS, AudioFile, AudioFileError, NON_AUDIO_EXTENSIONS,
+};
+use crate::models::settings::Settings;
+use crate::playlist::{self, Playlist};
+use crate::scan_cache::ScanCache;
+use crate::worker_pool::WorkerPool;
+
+#[derive(Clone, Debug)]
+pub struct ScanOptions {
+    pub recursive: bool,
+    pub skip_hidden: bool,
+    pub probe_unknown: bool,
+    pub extra_extensions: Vec<String>,
+    pub excluded_extensions: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InputKind {
+    Audio,
+    Unknown,
+    Rejected,
+}
+
+impl ScanOptions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            recursive: settings.import_recursive,
+            skip_hidden: settings.import_skip_hidden,
+            probe_unknown: settings.import_probe_unknown,
+            extra_extensions: parse_extension_list(&settings.import_extra_extensions),
+            excluded_extensions: parse_extension_list(&settings.import_excluded_extensions),
+        }
+    }
+
+    /// Extension fast-path, known audio and non-audio extensions skip the guesswork of probing
+    pub fn classify(&self, path: &Path) -> InputKind {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match extension {
+            Some(e) if self.excluded_extensions.contains(&e) => InputKind::Rejected,
+            Some(e)
+                if AUDIO_EXTENSIONS.contains(&e.as_str()) || self.extra_extensions.contains(&e) =>
+            {
+                InputKind::Audio
+            }
+            Some(e) if NON_AUDIO_EXTENSIONS.contains(&e.as_str()) => InputKind::Rejected,
+            _ if self.probe_unknown => InputKind::Unknown,
+            _ => InputKind::Rejected,
+        }
+    }
+}
+
+/// Splits a list like "mp4, .MKV" into lowercase extensions without the dot
+pub fn parse_extension_list(list: &str) -> Vec<String> {
+    list.split([',', ' ', ';'])
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+enum ScanMessage {
+    File(AudioFile),
+    Playlist(Playlist),
+    Error(PathBuf, AudioFileError),
+}
+
+/// Probes files on a pool of worker threads, sending each `AudioFile` back as soon as it has been
+/// read so the table fills in while the rest of the library is still being scanned
+pub struct Scanner {
+    pool: WorkerPool<ScanMessage>,
+    pub errors: Vec<(PathBuf, AudioFileError)>,
+    pub playlists: Vec<Playlist>,
+}
+
+impl Scanner {
+    pub fn start(paths: Vec<PathBuf>, options: ScanOptions, cache: Arc<Mutex<ScanCache>>) -> Self {
+        let collect_options = options.clone();
+        let list = move |tx: &mpsc::Sender<ScanMessage>| {
+            // each file along with the folder it was found in
+            let mut files: Vec<(PathBuf, Option<PathBuf>)> = Vec::new();
+            let mut errors = Vec::new();
+            for path in paths {
+                if path.is_dir() {
+                    let mut found = Vec::new();
+                    collect_directory(&path, &collect_options, &mut found, &mut errors);
+                    files.extend(found.into_iter().map(|f| (f, Some(path.clone()))));
+                } else {
+                    files.push((path, None));
+                }
+            }
+
+            // playlists are read here, and the files they list are imported along with them
+            let mut playlists = Vec::new();
+            files.retain(|(path, root)| {
+                if !playlist::is_playlist(path) {
+                    return true;
+                }
+                match Playlist::read(path, root.clone()) {
+                    Ok(playlist) => playlists.push(playlist),
+                    Err(e) => errors.push((path.clone(), e.into())),
+                }
+                false
+            });
+
+            let mut known: HashSet<PathBuf> = files.iter().map(|(path, _)| path.clone()).collect();
+            for playlist in &playlists {
+                for path in playlist.entries.iter().filter_map(|e| e.path.as_ref()) {
+                    if path.is_file() && known.insert(path.clone()) {
+                        files.push((path.clone(), None));
+                    }
+                }
+            }
+
+            for playlist in playlists {
+                let _ = tx.send(ScanMessage::Playlist(playlist));
+            }
+            for (path, e) in errors {
+                let _ = tx.send(ScanMessage::Error(path, e));
+            }
+
+            files
+        };
+
+        let probe_file = move |(path, root): (PathBuf, Option<PathBuf>)| {
+            let kind = options.classify(&path);
+            let result = match kind {
+                InputKind::Rejected => Err(AudioFileError::NotAnAudioFile),
+                _ => probe(path.clone(), &cache),
+            };
+
+            match result {
+                Ok(file) => Some(ScanMessage::File(AudioFile { root, ..file })),
+                // files with unknown extensions failing to probe just aren't audio
+                Err(e) if e.is_ignorable() || kind == InputKind::Unknown => None,
+                Err(e) => Some(ScanMessage::Error(path, e)),
+            }
+        };
+
+        Self {
+            pool: WorkerPool::start(list, probe_file),
+            errors: Vec::new(),
+            playlists: Vec::new(),
+        }
+    }
+
+    /// Returns the files probed since the last call, called every frame
+    pub fn receive(&mut self) -> Vec<AudioFile> {
+        let mut files = Vec::new();
+
+        for message in self.pool.receive() {
+            match message {
+                ScanMessage::File(file) => files.push(file),
+                ScanMessage::Playlist(playlist) => self.playlists.push(playlist),
+                ScanMessage::Error(path, e) => self.errors.push((path, e)),
+            }
+        }
+
+        files
+    }
+
+    pub fn cancel(&self) {
+        self.pool.cancel();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pool.is_finished()
+    }
+
+    pub fn total(&self) -> usize {
+        self.pool.total()
+    }
+
+    pub fn scanned(&self) -> usize {
+        self.pool.done()
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with('.'))
+}
+
+/// Every file in the directory that isn't hidden or skipped by the options, sorted by path
+pub fn collect_directory(
+    directory: &Path,
+    options: &ScanOptions,
+    files: &mut Vec<PathBuf>,
+    errors: &mut Vec<(PathBuf, AudioFileError)>,
+) {
+    let entries = match directory.read_dir() {
+        Ok(entries) => entries,
+        Err(e) => {
+            errors.push((directory.to_path_buf(), e.into()));
+            return;
+        }
+    };
+
+    let mut entries: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    entries.sort();
+
+    for path in entries {
+        if options.skip_hidden && is_hidden(&path) {
+            continue;
+        }
+
+        if path.is_dir() {
+            // following symlinked directories could loop forever
+            if options.recursive && !path.is_symlink() {
+                collect_directory(&path, options, files, errors);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// Only opens the file with FFmpeg if it isn't in the cache or has changed since it was cached
+pub fn probe(path: PathBuf, cache: &Mutex<ScanCache>) -> Result<AudioFile, AudioFileError> {
+    let metadata = fs::metadata(&path)?;
+
+    if let Some(file) = cache.lock().unwrap().get(&path, &metadata) {
+        return Ok(file);
+    }
+
+    let file = AudioFile::new(path)?;
+    cache.lock().unwrap().insert(&file, &metadata);
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn options(recursive: bool) -> ScanOptions {
+        ScanOptions {
+            recursive,
+            skip_hidden: true,
+            probe_unknown: false,
+            extra_extensions: Vec::new(),
+            excluded_extensions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn folders_are_collected_in_order_without_hidden_files() {
+        let temp = TempDir::new("scanner-collect");
+        let library = temp.path();
+        fs::create_dir_all(library.join("b album")).unwrap();
+        fs::create_dir_all(library.join(".hidden")).unwrap();
+        for file in [
+            "b album/2.flac",
+            "b album/1.flac",
+            "a.mp3",
+            ".hidden/c.flac",
+            ".d.flac",
+        ] {
+            fs::write(library.join(file), b"").unwrap();
+        }
+
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        collect_directory(library, &options(true), &mut files, &mut errors);
+        assert_eq!(
+            files,
+            vec![
+                library.join("a.mp3"),
+                library.join("b album/1.flac"),
+                library.join("b album/2.flac"),
+            ]
+        );
+        assert!(errors.is_empty());
+
+        files.clear();
+        collect_directory(library, &options(false), &mut files, &mut errors);
+        assert_eq!(files, vec![library.join("a.mp3")]);
+    }
+
+    #[test]
+    fn unreadable_folders_are_reported() {
+        let temp = TempDir::new("scanner-missing");
+        let missing = temp.path().join("missing");
+
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        collect_directory(&missing, &options(true), &mut files, &mut errors);
+
+        assert!(files.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, missing);
+        assert!(matches!(errors[0].1, AudioFileError::NotFound));
+        assert!(!errors[0].1.is_ignorable());
+    }
+
+    #[test]
+    fn extension_lists_are_normalised() {
+        assert_eq!(
+            parse_extension_list("mp4, .MKV;webm  "),
+            vec!["mp4", "mkv", "webm"]
+        );
+        assert!(parse_extension_list(" , ").is_empty());
+    }
+
+    #[test]
+    fn inputs_are_classified_by_extension_first() {
+        let options = ScanOptions {
+            probe_unknown: true,
+            extra_extensions: vec!["mp4".to_string()],
+            excluded_extensions: vec!["wav".to_string()],
+            ..options(true)
+        };
+
+        assert_eq!(options.classify(Path::new("a.FLAC")), InputKind::Audio);
+        assert_eq!(options.classify(Path::new("video.mp4")), InputKind::Audio);
+        assert_eq!(options.classify(Path::new("a.wav")), InputKind::Rejected);
+        assert_eq!(
+            options.classify(Path::new("cover.jpg")),
+            InputKind::Rejected
+        );
+        assert_eq!(options.classify(Path::new("track")), InputKind::Unknown);
+
+        let known_only = ScanOptions {
+            probe_unknown: false,
+            ..options
+        };
+        assert_eq!(known_only.classify(Path::new("track")), InputKind::Rejected);
+    }
+}
@@ -0,0 +1,209 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::manifest;
+use crate::models::audio_file::AudioFile;
+use crate::models::settings::SourceAction;
+
+/// Moves the file into `folder`, at the same path relative to `root` as it had
+pub fn move_preserving_path(path: &Path, root: &Path, folder: &Path) -> io::Result<PathBuf> {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let destination = folder.join(relative);
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    move_file(path, &destination)?;
+    Ok(destination)
+}
+
+/// Renames the file, copying it when it's on another drive. The destination is reserved first so a
+/// file already there, or one created there meanwhile, is never replaced
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::OpenOptions::new().write(true).create_new(true).open(to) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", to.display()),
+            ));
+        }
+        Err(e) => return Err(e),
+    }
+
+    let result = match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to).and_then(|_| fs::remove_file(from))
+        }
+        result => result,
+    };
+
+    // the source is still there, so the destination only holds the placeholder or a copy of it
+    if result.is_err() && from.exists() {
+        let _ = fs::remove_file(to);
+    }
+    result
+}
+
+/// `$XDG_DATA_HOME/Trash`, the home trash of the freedesktop.org trash specification
+fn trash_directory() -> io::Result<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .map(|data| data.join("Trash"))
+        .ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No home folder to find the trash in",
+        ))
+}
+
+/// Percent-encodes everything but unreserved characters and slashes, as the `Path` key of a
+/// `.trashinfo` file expects
+#[cfg(unix)]
+fn encode_trash_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str()
+        .as_bytes()
+        .iter()
+        .map(|&byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Moves the file into the home trash so it can be restored from the file manager. Files on other
+/// drives are copied there rather than put in the drive's own trash
+#[cfg(unix)]
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    let path = fs::canonicalize(path)?;
+    let trash = trash_directory()?;
+    let files = trash.join("files");
+    let info = trash.join("info");
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info)?;
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    // the info file is created first to claim the name, as the specification asks
+    let mut i = 1;
+    let (trashed, info_path, mut info_file) = loop {
+        let candidate = match i {
+            1 => name.clone(),
+            _ => format!("{name}.{i}"),
+        };
+        i += 1;
+
+        let trashed = files.join(&candidate);
+        if trashed.exists() {
+            continue;
+        }
+
+        let info_path = info.join(format!("{candidate}.trashinfo"));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => break (trashed, info_path, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    };
+
+    // the specification asks for local time, which std can't give, UTC is only off by the offset
+    let result = write!(
+        info_file,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_trash_path(&path),
+        manifest::format_timestamp(SystemTime::now())
+    )
+    .and_then(|_| move_file(&path, &trashed));
+
+    if result.is_err() {
+        let _ = fs::remove_file(info_path);
+    }
+    result
+}
+
+#[cfg(not(unix))]
+pub fn move_to_trash(_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Moving to the trash is only supported on Linux",
+    ))
+}
+
+/// Keeps, moves or deletes the source once its outputs have been verified. Files opened on their
+/// own rather than from a folder are moved into the folder by name
+pub fn handle_source(file: &AudioFile, action: &SourceAction, folder: &str) -> io::Result<()> {
+    match action {
+        SourceAction::Keep => Ok(()),
+        SourceAction::MoveToFolder => {
+            if folder.trim().is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "No folder to move the source to",
+                ));
+            }
+
+            let root = match &file.root {
+                Some(root) => root.as_path(),
+                None => file.path.parent().unwrap_or(Path::new("")),
+            };
+            move_preserving_path(&file.path, root, Path::new(folder.trim())).map(|_| ())
+        }
+        SourceAction::Trash => move_to_trash(&file.path),
+        SourceAction::Delete => fs::remove_file(&file.path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn moved_files_keep_their_path_under_the_root() {
+        let temp = TempDir::new("move-preserving-path");
+        let root = temp.path().join("inbox");
+        fs::create_dir_all(root.join("album")).unwrap();
+        let source = root.join("album/a.flac");
+        fs::write(&source, b"audio").unwrap();
+
+        let done = temp.path().join("done");
+        let moved = move_preserving_path(&source, &root, &done).unwrap();
+
+        assert_eq!(moved, done.join("album/a.flac"));
+        assert_eq!(fs::read(&moved).unwrap(), b"audio");
+        assert!(!source.exists());
+    }
+
+    #[test]
+    fn existing_destination_survives() {
+        let temp = TempDir::new("move-existing");
+        let root = temp.path().join("inbox");
+        let done = temp.path().join("done");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&done).unwrap();
+        let source = root.join("a.flac");
+        fs::write(&source, b"new").unwrap();
+        fs::write(done.join("a.flac"), b"old").unwrap();
+
+        let error = move_preserving_path(&source, &root, &done).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(done.join("a.flac")).unwrap(), b"old");
+        assert_eq!(fs::read(&source).unwrap(), b"new");
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use crate::manifest::{self, Manifest};
+use crate::models::audio_file::{AudioFile, AudioFileError};
+use crate::models::settings::Settings;
+use crate::models::task::{self, Task};
+use crate::playlist::{self, Playlist};
+use crate::scan_cache::ScanCache;
+use crate::scanner::{self, InputKind, ScanOptions};
+use crate::tasks_manager::TasksManager;
+
+/// What mirroring a library into the output directory would do, shown before anything is changed
+pub struct SyncPlan {
+    pub source_root: PathBuf,
+    pub settings: Settings,
+    /// Sources that haven't been converted yet
+    pub added: Vec<AudioFile>,
+    /// Sources that changed, or were converted with other settings, since the last sync
+    pub updated: Vec<AudioFile>,
+    /// Sources that have disappeared, along with the file they were converted to
+    pub removed: Vec<(PathBuf, PathBuf)>,
+    pub up_to_date: usize,
+    /// Rewritten into the mirror every sync, pointing at the converted files
+    pub playlists: Vec<Playlist>,
+    pub errors: Vec<(PathBuf, AudioFileError)>,
+    manifest: Manifest,
+}
+
+/// Compares the library with the output directory's manifest on another thread, only the files
+/// that need converting are probed
+pub fn plan(
+    source_root: PathBuf,
+    settings: Settings,
+    options: ScanOptions,
+    cache: Arc<Mutex<ScanCache>>,
+) -> mpsc::Receiver<SyncPlan> {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let destination = PathBuf::from(&settings.out_directory);
+        let mut manifest = Manifest::load(&destination);
+        let settings_hash = manifest::settings_hash(&settings);
+
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        scanner::collect_directory(&source_root, &options, &mut files, &mut errors);
+        let mirror = mirror_in_library(&source_root, &destination);
+
+        let synced = manifest.outputs_by_source(&destination);
+        let mut added = Vec::new();
+        let mut updated = Vec::new();
+        let mut up_to_date = 0;
+        let mut playlists = Vec::new();
+
+        for path in files {
+            // a mirror inside the library mustn't be mirrored into itself
+            if mirror
+                .as_ref()
+                .is_some_and(|mirror| path.starts_with(mirror))
+            {
+                continue;
+            }
+
+            if playlist::is_playlist(&path) {
+                match Playlist::read(&path, Some(source_root.clone())) {
+                    Ok(playlist) => playlists.push(playlist),
+                    Err(e) => errors.push((path, e.into())),
+                }
+                continue;
+            }
+
+            let kind = options.classify(&path);
+            if kind == InputKind::Rejected {
+                continue;
+            }
+
+            // the output's name only depends on the source's path when mirroring
+            let output = task::output_path(
+                &AudioFile {
+                    path: path.clone(),
+                    ..Default::default()
+                },
+                &settings,
+                Some(&source_root),
+            );
+            if manifest.is_up_to_date(&path, &output, &destination, settings_hash) {
+                up_to_date += 1;
+                continue;
+            }
+
+            let was_synced = synced.contains_key(&path);
+            match scanner::probe(path.clone(), &cache) {
+                Ok(file) if was_synced => updated.push(file),
+                Ok(file) => added.push(file),
+                Err(e) if e.is_ignorable() || kind == InputKind::Unknown => {}
+                Err(e) => errors.push((path, e)),
+            }
+        }
+
+        let _ = tx.send(SyncPlan {
+            removed: manifest.orphans(&source_root, &destination),
+            source_root,
+            settings,
+            added,
+            updated,
+            up_to_date,
+            playlists,
+            errors,
+            manifest,
+        });
+    });
+
+    rx
+}
+
+/// The output directory as a path under `source_root` when it's inside the library, however either
+/// of them is written, e.g. the relative "./" against an absolute library path
+fn mirror_in_library(source_root: &Path, destination: &Path) -> Option<PathBuf> {
+    let root = fs::canonicalize(source_root).ok()?;
+    let destination = fs::canonicalize(destination).ok()?;
+
+    destination
+        .strip_prefix(&root)
+        .ok()
+        .map(|relative| source_root.join(relative))
+}
+
+/// Removes the folders left empty by deleting a file, stopping at the output directory
+fn remove_empty_parents(path: &Path, destination: &Path) {
+    let mut directory = path.parent();
+    while let Some(d) = directory
+        && d.starts_with(destination)
+        && d != destination
+    {
+        if fs::remove_dir(d).is_err() {
+            break;
+        }
+        directory = d.parent();
+    }
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+
+    /// Queues the added and updated files, and deletes the converted files of removed sources if
+    /// asked to. Returns the files that couldn't be deleted
+    pub fn run(
+        self,
+        delete_removed: bool,
+        tasks_manager: &mut TasksManager,
+    ) -> Vec<(PathBuf, io::Error)> {
+        let destination = PathBuf::from(&self.settings.out_directory);
+        let mut manifest = self.manifest;
+        let mut errors = Vec::new();
+
+        if delete_removed {
+            for (_, output) in self.removed {
+                match fs::remove_file(&output) {
+                    Ok(()) => remove_empty_parents(&output, &destination),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        errors.push((output, e));
+                        continue;
+                    }
+                }
+                manifest.remove(&output, &destination);
+            }
+        }
+
+        let synced = manifest.outputs_by_source(&destination);
+
+        for file in self.added.into_iter().chain(self.updated) {
+            let task = Task::mirrored(file, self.settings.clone(), self.source_root.clone());
+
+            // converting to another container gives the file a new name, the old one would be
+            // left behind
+            if delete_removed && let Some(outputs) = synced.get(&task.file.path) {
+                let output = task.output_path(&self.settings);
+                for previous in outputs.iter().filter(|previous| **previous != output) {
+                    match fs::remove_file(previous) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                            errors.push((previous.clone(), e))
+                        }
+                        _ => manifest.remove(previous, &destination),
+                    }
+                }
+            }
+
+            tasks_manager.queue_task(task);
+        }
+        tasks_manager.set_manifest(destination, manifest);
+
+        // entries that are up to date weren't probed, they're found by their path in the mirror
+        for playlist in self.playlists {
+            tasks_manager.queue_playlist(
+                playlist,
+                &[],
+                &self.settings,
+                Some(self.source_root.clone()),
+            );
+        }
+
+        errors
+    }
+}
@@ -1,29 +1,307 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::models::{audio_file::AudioFile, settings::Settings, task::Task};
+use crate::attributes;
+use crate::cover_art_cache::CoverArtCache;
+use crate::manifest::{self, Manifest, ManifestEntry};
+use crate::models::{audio_file::AudioFile, settings::Settings, task, task::Task};
+use crate::playlist::Playlist;
+
+/// What happened to a finished task, kept until the user clears the results
+pub struct TaskResult {
+    pub path: PathBuf,
+    pub target: Option<String>,
+    pub error: Option<String>,
+    /// Already converted with the same settings according to the output directory's manifest
+    pub skipped: bool,
+    /// Why the source was kept, or couldn't be moved or deleted, only set on the first target
+    pub source_error: Option<String>,
+    /// Decoding errors that were skipped, the output is missing the audio they affected
+    pub concealed_errors: usize,
+}
+
+/// A playlist rewritten into an output directory once the queue was done
+pub struct PlaylistResult {
+    pub path: PathBuf,
+    pub output: PathBuf,
+    pub target: Option<String>,
+    /// Entries left out because there's no converted file for them, with the reason
+    pub unresolved: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// A playlist waiting for its entries to be converted, with the loaded files they refer to
+struct PendingPlaylist {
+    playlist: Playlist,
+    target: Option<String>,
+    settings: Settings,
+    files: HashMap<PathBuf, AudioFile>,
+    /// Set when mirroring a library, entries that weren't probed are found by path alone
+    source_root: Option<PathBuf>,
+}
 
-#[derive(Debug)]
 pub struct TasksManager {
     pub queue: VecDeque<Task>,
     pub active_tasks: Vec<Task>,
+    pub results: Vec<TaskResult>,
+    pub playlist_results: Vec<PlaylistResult>,
+    pending_playlists: Vec<PendingPlaylist>,
+    cover_art_cache: Arc<CoverArtCache>,
+    /// Manifests of the output directories, written once the queue is empty
+    manifests: HashMap<PathBuf, Manifest>,
+    unsaved_manifests: HashSet<PathBuf>,
+    /// Mirrored folders written into and the library folders they mirror, their times are copied
+    /// once the queue is empty since every file written changes them
+    directory_times: HashMap<PathBuf, PathBuf>,
 }
 
 impl TasksManager {
-    pub fn new() -> Self {
+    pub fn new(cover_art_cache: Arc<CoverArtCache>) -> Self {
         return TasksManager {
             queue: VecDeque::new(),
             active_tasks: Vec::new(),
+            results: Vec::new(),
+            playlist_results: Vec::new(),
+            pending_playlists: Vec::new(),
+            cover_art_cache,
+            manifests: HashMap::new(),
+            unsaved_manifests: HashSet::new(),
+            directory_times: HashMap::new(),
         };
     }
 
-    pub fn queue_audio_file(&mut self, file: AudioFile) {
-        let task = Task::new(file);
+    /// Queues the file for every output target, the settings are captured now so changing them
+    /// doesn't affect files that are already queued. Targets the file is up to date in are skipped
+    /// unless disabled in the settings
+    pub fn queue_audio_file(&mut self, file: AudioFile, settings: &Settings) {
+        let mut targets = settings.target_settings();
+        if settings.run_skip_up_to_date {
+            targets.retain(|(target, target_settings)| {
+                if !self.is_up_to_date(&file, target_settings) {
+                    return true;
+                }
+
+                self.results.push(TaskResult {
+                    path: file.path.clone(),
+                    target: target.clone(),
+                    error: None,
+                    skipped: true,
+                    source_error: None,
+                    concealed_errors: 0,
+                });
+                false
+            });
+        }
+
+        if !targets.is_empty() {
+            self.queue.push_back(Task::new(file, targets));
+        }
+    }
+
+    pub fn queue_task(&mut self, task: Task) {
         self.queue.push_back(task);
     }
 
+    /// Writes the playlist into every target once the queue is empty, pointing at the converted
+    /// files. `files` are the loaded files its entries may refer to
+    pub fn queue_playlist(
+        &mut self,
+        playlist: Playlist,
+        files: &[AudioFile],
+        settings: &Settings,
+        source_root: Option<PathBuf>,
+    ) {
+        let files: HashMap<PathBuf, AudioFile> = files
+            .iter()
+            .filter(|f| {
+                playlist
+                    .entries
+                    .iter()
+                    .any(|e| e.path.as_ref() == Some(&f.path))
+            })
+            .map(|f| (f.path.clone(), f.clone()))
+            .collect();
+
+        for (target, settings) in settings.target_settings() {
+            self.pending_playlists.push(PendingPlaylist {
+                playlist: playlist.clone(),
+                target,
+                settings,
+                files: files.clone(),
+                source_root: source_root.clone(),
+            });
+        }
+    }
+
+    /// Replaces the output directory's manifest, e.g. after a sync removed entries from it
+    pub fn set_manifest(&mut self, directory: PathBuf, manifest: Manifest) {
+        self.unsaved_manifests.insert(directory.clone());
+        self.manifests.insert(directory, manifest);
+    }
+
+    /// The manifest of the output directory, read the first time it's needed
+    fn manifest(&mut self, directory: &Path) -> &mut Manifest {
+        self.manifests
+            .entry(directory.to_path_buf())
+            .or_insert_with(|| Manifest::load(directory))
+    }
+
+    fn is_up_to_date(&mut self, file: &AudioFile, settings: &Settings) -> bool {
+        let directory = PathBuf::from(&settings.out_directory);
+        let settings_hash = manifest::settings_hash(settings);
+
+        let output = task::output_path(file, settings, None);
+        let up_to_date =
+            self.manifest(&directory)
+                .is_up_to_date(&file.path, &output, &directory, settings_hash);
+        // the entry's modification time may have been refreshed
+        if up_to_date {
+            self.unsaved_manifests.insert(directory);
+        }
+
+        up_to_date
+    }
+
+    /// Adds an entry for every target the file was converted to
+    fn record_in_manifest(&mut self, task: &Task) {
+        let Some((source_hash, metadata)) = task.source() else {
+            return;
+        };
+
+        for ((_, settings), result) in task.targets.iter().zip(task.results()) {
+            if result.is_err() {
+                continue;
+            }
+
+            let directory = PathBuf::from(&settings.out_directory);
+            let entry = ManifestEntry::new(
+                metadata,
+                *source_hash,
+                &task.output_path(settings),
+                &directory,
+                settings,
+            );
+
+            self.manifest(&directory).insert(&task.file.path, entry);
+            self.unsaved_manifests.insert(directory);
+        }
+    }
+
+    /// Remembers every folder between the file and the library's root in targets keeping the
+    /// source's times
+    fn record_directories(&mut self, task: &Task) {
+        let (Some(root), Some(parent)) = (&task.source_root, task.file.path.parent()) else {
+            return;
+        };
+
+        for (_, settings) in &task.targets {
+            if !settings.out_preserve_times {
+                continue;
+            }
+
+            let mut output = task.target_directory(settings);
+            for source in parent.ancestors().take_while(|dir| dir.starts_with(root)) {
+                self.directory_times
+                    .insert(output.clone(), source.to_path_buf());
+                output.pop();
+            }
+        }
+    }
+
+    /// Rewrites the pending playlists with the paths of the converted files, entries without one
+    /// are left out and reported
+    fn write_playlists(&mut self) {
+        for pending in self.pending_playlists.drain(..) {
+            let mut entries = Vec::new();
+            let mut unresolved = Vec::new();
+
+            for entry in &pending.playlist.entries {
+                let Some(path) = &entry.path else {
+                    unresolved.push(format!(
+                        "{}: a URL or a path from another system",
+                        entry.location
+                    ));
+                    continue;
+                };
+
+                let file = match pending.files.get(path) {
+                    Some(file) => file.clone(),
+                    None if pending.source_root.is_some() && path.is_file() => AudioFile {
+                        path: path.clone(),
+                        ..Default::default()
+                    },
+                    None if !path.is_file() => {
+                        unresolved.push(format!("{}: file not found", entry.location));
+                        continue;
+                    }
+                    None => {
+                        unresolved.push(format!("{}: not an opened audio file", entry.location));
+                        continue;
+                    }
+                };
+
+                let output =
+                    task::output_path(&file, &pending.settings, pending.source_root.as_deref());
+                if output.is_file() {
+                    entries.push((output, entry));
+                } else {
+                    unresolved.push(format!("{}: not converted", entry.location));
+                }
+            }
+
+            let output = pending.playlist.output_path(&pending.settings);
+            let error = pending
+                .playlist
+                .write(&output, &entries)
+                .err()
+                .map(|e| e.to_string());
+
+            self.playlist_results.push(PlaylistResult {
+                path: pending.playlist.path.clone(),
+                output,
+                target: pending.target,
+                unresolved,
+                error,
+            });
+        }
+    }
+
+    fn save_manifests(&mut self) {
+        for directory in self.unsaved_manifests.drain() {
+            if let Some(manifest) = self.manifests.get(&directory) {
+                let _ = manifest.save(&directory);
+            }
+        }
+    }
+
     /// Updates the active_tasks pool according to settings, called every frame
     pub fn update(&mut self, settings: &Settings) {
-        self.active_tasks.retain(|task| !task.is_complete());
+        let mut finished = Vec::new();
+        let mut i = 0;
+        while i < self.active_tasks.len() {
+            if self.active_tasks[i].is_complete() {
+                finished.push(self.active_tasks.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+
+        for task in finished {
+            for (i, ((target, _), result)) in task.targets.iter().zip(task.results()).enumerate() {
+                self.results.push(TaskResult {
+                    path: task.file.path.clone(),
+                    target: target.clone(),
+                    error: result.clone().err(),
+                    skipped: false,
+                    source_error: task.source_error().filter(|_| i == 0).map(str::to_string),
+                    concealed_errors: task.concealed_errors(),
+                });
+            }
+
+            self.record_in_manifest(&task);
+            self.record_directories(&task);
+        }
 
         while self.active_tasks.len() < settings.run_concurrent_task_count {
             let mut task = match self.queue.pop_front() {
@@ -31,8 +309,21 @@ impl TasksManager {
                 None => break,
             };
 
-            task.start_transcode(settings);
+            task.start_transcode(&self.cover_art_cache);
             self.active_tasks.push(task);
         }
+
+        if self.active_tasks.is_empty() && !self.unsaved_manifests.is_empty() {
+            self.save_manifests();
+        }
+        if self.active_tasks.is_empty() && !self.pending_playlists.is_empty() {
+            self.write_playlists();
+        }
+        // after the manifests and playlists, which are written into the mirror's folders
+        if self.active_tasks.is_empty() {
+            for (output, source) in self.directory_times.drain() {
+                let _ = attributes::copy_directory_times(&source, &output);
+            }
+        }
     }
 }
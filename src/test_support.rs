@@ -0,0 +1,34 @@
+//! Helpers shared by the unit tests
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_DIRECTORY: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty folder under the system's temporary directory, removed along with its content when
+/// dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates a folder no other test, or other run of the tests, uses
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "audio-converter-{name}-{}-{}",
+            std::process::id(),
+            NEXT_DIRECTORY.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,29 +1,126 @@
-use std::path::Path;
-use std::{io::Cursor, ptr};
+use std::fs;
+use std::path::PathBuf;
+use std::ptr;
 
 use base64::prelude::*;
-use byteorder::{BigEndian, WriteBytesExt};
 use ffmpeg_next::ffi::{
-    av_dict_set, av_frame_unref, av_init_packet, av_malloc, av_write_frame, avformat_new_stream,
+    AV_DICT_MULTIKEY, av_dict_set, av_frame_unref, av_init_packet, av_malloc, av_write_frame,
+    avformat_new_stream,
 };
 use ffmpeg_next::{codec, filter, format, frame, media};
-use image::ImageReader;
 
+use crate::cover_art::{self, CoverArtOptions, Picture};
+use crate::cover_art_cache::CoverArtCache;
 use crate::models::audio_file::{self, AudioCodec, AudioContainer, AudioFile, AudioSampleRate};
+use crate::models::settings::Settings;
+
+/// Everything needed to produce one output file from a source
+pub struct OutputOptions {
+    pub codec: AudioCodec,
+    pub container: AudioContainer,
+    pub sample_rate: AudioSampleRate,
+    pub bitrate: usize,
+    pub directory: PathBuf,
+    pub embed_cover_art: bool,
+    pub sidecar_cover_art: bool,
+    pub cover_art_options: CoverArtOptions,
+    /// Decode the output again once written, to check nothing went wrong
+    pub verify: bool,
+    /// Give the output the source's access and modification times
+    pub preserve_times: bool,
+    /// Give the output the source's Unix permission bits
+    pub preserve_permissions: bool,
+}
+
+impl OutputOptions {
+    pub fn from_settings(settings: &Settings, directory: PathBuf) -> Self {
+        Self {
+            codec: settings.out_codec.clone(),
+            container: settings.out_container.clone(),
+            sample_rate: settings.out_sample_rate.clone(),
+            bitrate: settings.out_bitrate,
+            directory,
+            embed_cover_art: settings.out_embed_art,
+            sidecar_cover_art: settings.out_sidecar_art,
+            cover_art_options: CoverArtOptions::from_settings(settings),
+            verify: settings.run_verify_outputs,
+            preserve_times: settings.out_preserve_times,
+            preserve_permissions: settings.out_preserve_permissions,
+        }
+    }
+
+    /// The source's file name with the container's extension, inside the output directory
+    pub fn output_path(&self, file: &AudioFile) -> PathBuf {
+        let mut name = match file.path.file_stem().and_then(|s| s.to_str()) {
+            Some(stem) => stem.to_string(),
+            None => file
+                .title
+                .clone()
+                .unwrap_or(crate::app::NO_TITLE.to_string()),
+        };
+
+        name += match self.container {
+            AudioContainer::FLAC => ".flac",
+            AudioContainer::MP3 => ".mp3",
+            AudioContainer::M4A => ".m4a",
+            AudioContainer::OGG => ".ogg",
+            AudioContainer::OPUS => ".opus",
+        };
+
+        self.directory.join(name)
+    }
+}
+
+// Transcoding code is based on ffmpeg-next/examples/transcode-audio.rs, extended so a single
+// decoder feeds any number of encoders through an asplit filter
+
+/// An encoder and the file it writes to, fed from the shared decoder
+struct Output {
+    path: PathBuf,
+    octx: format::context::Output,
+    encoder: codec::encoder::Audio,
+    out_time_base: ffmpeg_next::Rational,
+    picture_streams: Vec<(usize, Picture)>,
+    embeds_cover_art: bool,
+}
 
-// Transcoding code almost word-for-word copied from ffmpeg-next/examples/transcode-audio.rs
 struct Transcoder {
     stream: usize,
     filter: filter::Graph,
     decoder: codec::decoder::Audio,
-    encoder: codec::encoder::Audio,
     in_time_base: ffmpeg_next::Rational,
-    out_time_base: ffmpeg_next::Rational,
+    /// One per `OutputOptions`, an output is replaced with its error as soon as it fails so the
+    /// other outputs can carry on
+    outputs: Vec<Result<Output, ffmpeg_next::Error>>,
+    /// Indices of the outputs that have a sink in the filter graph
+    sinks: Vec<usize>,
+    /// Decoding errors skipped so far, and how many are allowed before giving up
+    concealed_errors: usize,
+    max_errors: Option<usize>,
+    /// Samples per channel decoded from the source so far
+    decoded_samples: u64,
+}
+
+/// The result of each output in the same order as the `OutputOptions`, and how many decoding
+/// errors were skipped over
+pub struct Converted {
+    pub results: Vec<Result<(), ffmpeg_next::Error>>,
+    /// Whether each output had at least one picture embedded
+    pub embedded_cover_art: Vec<bool>,
+    pub concealed_errors: usize,
+    /// Seconds of audio decoded from the source, None when it couldn't be opened
+    pub source_duration: Option<f64>,
+}
+
+fn sink_name(output: usize) -> String {
+    format!("out{output}")
 }
 
+/// abuffer -> asplit -> aresample -> aformat -> abuffersink, with one resample/format/sink branch
+/// per output so every encoder gets the sample rate, format and channel layout it needs
 fn filter(
     decoder: &codec::decoder::Audio,
-    encoder: &codec::encoder::Audio,
+    outputs: &[(usize, &codec::encoder::Audio)],
 ) -> Result<filter::Graph, ffmpeg_next::Error> {
     let mut filter = filter::Graph::new();
 
@@ -35,65 +132,112 @@ fn filter(
         decoder.channel_layout().bits()
     );
 
-    filter.add(&filter::find("abuffer").unwrap(), "in", &in_args)?;
-    filter.add(&filter::find("abuffersink").unwrap(), "out", "")?;
-
-    let filter_spec = format!(
-        "aresample={},aformat=sample_fmts={}:channel_layouts={}",
-        encoder.rate(),
-        encoder.format().name(),
-        encoder.channel_layout().bits(),
-    );
+    let mut input = filter.add(&filter::find("abuffer").unwrap(), "in", &in_args)?;
+    let mut split = filter.add(
+        &filter::find("asplit").unwrap(),
+        "split",
+        &outputs.len().to_string(),
+    )?;
+    input.link(0, &mut split, 0);
+
+    for (pad, (i, encoder)) in outputs.iter().enumerate() {
+        let mut resample = filter.add(
+            &filter::find("aresample").unwrap(),
+            &format!("resample{i}"),
+            &encoder.rate().to_string(),
+        )?;
+        let format_args = format!(
+            "sample_fmts={}:channel_layouts=0x{:x}",
+            encoder.format().name(),
+            encoder.channel_layout().bits(),
+        );
+        let mut format = filter.add(
+            &filter::find("aformat").unwrap(),
+            &format!("format{i}"),
+            &format_args,
+        )?;
+        let mut sink = filter.add(&filter::find("abuffersink").unwrap(), &sink_name(*i), "")?;
+
+        split.link(pad as u32, &mut resample, 0);
+        resample.link(0, &mut format, 0);
+        format.link(0, &mut sink, 0);
+    }
 
-    filter
-        .output("in", 0)?
-        .input("out", 0)?
-        .parse(&filter_spec)?;
     filter.validate()?;
 
-    if let Some(codec) = encoder.codec() {
-        if !codec
-            .capabilities()
-            .contains(ffmpeg_next::codec::capabilities::Capabilities::VARIABLE_FRAME_SIZE)
-        {
-            filter
-                .get("out")
-                .unwrap()
-                .sink()
-                .set_frame_size(encoder.frame_size());
+    for (i, encoder) in outputs {
+        if let Some(codec) = encoder.codec() {
+            if !codec
+                .capabilities()
+                .contains(ffmpeg_next::codec::capabilities::Capabilities::VARIABLE_FRAME_SIZE)
+            {
+                filter
+                    .get(&sink_name(*i))
+                    .unwrap()
+                    .sink()
+                    .set_frame_size(encoder.frame_size());
+            }
         }
     }
 
     Ok(filter)
 }
 
-fn transcoder(
+fn decoder(
     ictx: &mut format::context::Input,
-    octx: &mut format::context::Output,
-    out_codec: &AudioCodec,
-    out_sample_rate: &AudioSampleRate,
-    out_bitrate: usize,
-) -> Result<Transcoder, ffmpeg_next::Error> {
+) -> Result<(usize, codec::decoder::Audio), ffmpeg_next::Error> {
     let input = ictx
         .streams()
         .best(media::Type::Audio)
         .ok_or(ffmpeg_next::Error::StreamNotFound)?;
     let context = codec::context::Context::from_parameters(input.parameters())?;
     let mut decoder = context.decoder().audio()?;
-    let codec = codec::encoder::find(match out_codec {
+    decoder.set_parameters(input.parameters())?;
+
+    Ok((input.index(), decoder))
+}
+
+fn codec_id(out_codec: &AudioCodec) -> codec::Id {
+    match out_codec {
         audio_file::AudioCodec::FLAC => codec::Id::FLAC,
         audio_file::AudioCodec::MP3 => codec::Id::MP3,
         audio_file::AudioCodec::AAC => codec::Id::AAC,
         audio_file::AudioCodec::OPUS => codec::Id::OPUS,
         audio_file::AudioCodec::VORBIS => codec::Id::VORBIS,
-    })
-    .ok_or(ffmpeg_next::Error::EncoderNotFound)?
-    .audio()?;
+    }
+}
+
+/// The encoder FFmpeg picks for the codec and the libavcodec it comes from, e.g.
+/// "libopus (libavcodec 61.19.100)"
+pub fn encoder_version(out_codec: &AudioCodec) -> String {
+    let version = codec::version();
+    let libavcodec = format!(
+        "libavcodec {}.{}.{}",
+        version >> 16,
+        (version >> 8) & 0xff,
+        version & 0xff
+    );
+
+    match codec::encoder::find(codec_id(out_codec)) {
+        Some(encoder) => format!("{} ({libavcodec})", encoder.name()),
+        None => libavcodec,
+    }
+}
+
+fn encoder(
+    octx: &mut format::context::Output,
+    decoder: &codec::decoder::Audio,
+    out_codec: &AudioCodec,
+    out_sample_rate: &AudioSampleRate,
+    out_bitrate: usize,
+) -> Result<codec::encoder::Audio, ffmpeg_next::Error> {
+    let codec = codec::encoder::find(codec_id(out_codec))
+        .ok_or(ffmpeg_next::Error::EncoderNotFound)?
+        .audio()?;
     let global = octx
         .format()
         .flags()
         .contains(ffmpeg_next::format::flag::Flags::GLOBAL_HEADER);
-    decoder.set_parameters(input.parameters())?;
 
     let mut output = octx.add_stream(codec)?;
     let context = codec::context::Context::from_parameters(output.parameters())?;
@@ -130,277 +274,470 @@ fn transcoder(
     let encoder = encoder.open_as(codec)?;
     output.set_parameters(&encoder);
 
-    let filter = filter(&decoder, &encoder)?;
+    Ok(encoder)
+}
 
-    let in_time_base = decoder.time_base();
-    let out_time_base = output.time_base();
+impl Output {
+    /// Creates the output file and writes its header, with the source's metadata and cover art.
+    /// The file is removed again if anything after creating it fails
+    fn new(
+        file: &AudioFile,
+        metadata: &ffmpeg_next::DictionaryRef,
+        decoder: &codec::decoder::Audio,
+        options: &OutputOptions,
+        source_pictures: &[Picture],
+        cover_art_cache: &CoverArtCache,
+    ) -> Result<Self, ffmpeg_next::Error> {
+        let path = options.output_path(file);
+        let octx = format::output(&path)?;
+
+        Self::start(
+            path.clone(),
+            octx,
+            metadata,
+            decoder,
+            options,
+            source_pictures,
+            cover_art_cache,
+        )
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&path);
+        })
+    }
 
-    Ok(Transcoder {
-        stream: input.index(),
-        filter,
-        decoder,
-        encoder,
-        in_time_base,
-        out_time_base,
-    })
-}
+    fn start(
+        path: PathBuf,
+        mut octx: format::context::Output,
+        metadata: &ffmpeg_next::DictionaryRef,
+        decoder: &codec::decoder::Audio,
+        options: &OutputOptions,
+        source_pictures: &[Picture],
+        cover_art_cache: &CoverArtCache,
+    ) -> Result<Self, ffmpeg_next::Error> {
+        let encoder = encoder(
+            &mut octx,
+            decoder,
+            &options.codec,
+            &options.sample_rate,
+            options.bitrate,
+        )?;
+
+        let mut metadata = metadata.to_owned();
+        let pictures = if options.embed_cover_art || options.sidecar_cover_art {
+            prepare_pictures(
+                source_pictures,
+                &options.cover_art_options,
+                cover_art_cache,
+                &options.codec,
+            )
+        } else {
+            Vec::new()
+        };
+
+        if options.sidecar_cover_art
+            && let Some(cover) = cover_art::front_cover(&pictures)
+        {
+            let _ = cover_art::write_sidecar(&options.directory, cover);
+        }
 
-impl Transcoder {
-    fn send_frame_to_encoder(&mut self, frame: &ffmpeg_next::Frame) {
-        self.encoder.send_frame(frame).unwrap();
+        let out_codec = &options.codec;
+        let embeds_cover_art = options.embed_cover_art && !pictures.is_empty();
+        let mut picture_streams: Vec<(usize, Picture)> = Vec::new();
+        if options.embed_cover_art && !pictures.is_empty() {
+            if *out_codec == AudioCodec::FLAC
+                || *out_codec == AudioCodec::VORBIS
+                || *out_codec == AudioCodec::OPUS
+            {
+                // METADATA_BLOCK_PICTURE can appear once per picture, which needs AV_DICT_MULTIKEY
+                let key = std::ffi::CString::new("METADATA_BLOCK_PICTURE").unwrap();
+                unsafe {
+                    let mut dict = metadata.disown();
+                    for picture in &pictures {
+                        let block = BASE64_STANDARD.encode(picture.flac_picture_block());
+                        let val = std::ffi::CString::new(block).unwrap();
+                        av_dict_set(
+                            &mut dict,
+                            key.as_ptr(),
+                            val.as_ptr(),
+                            AV_DICT_MULTIKEY as i32,
+                        );
+                    }
+                    metadata = ffmpeg_next::Dictionary::own(dict);
+                }
+            } else if *out_codec == AudioCodec::AAC || *out_codec == AudioCodec::MP3 {
+                for picture in pictures {
+                    let cover_stream =
+                        unsafe { avformat_new_stream(octx.as_mut_ptr(), ptr::null()) };
+                    if cover_stream.is_null() {
+                        return Err(ffmpeg_next::Error::Unknown);
+                    }
+
+                    unsafe {
+                        let par = (*cover_stream).codecpar;
+                        (*par).codec_type = ffmpeg_next::ffi::AVMediaType::AVMEDIA_TYPE_VIDEO;
+                        (*par).codec_id = match picture.mime_type.as_str() {
+                            "image/png" => ffmpeg_next::ffi::AVCodecID::AV_CODEC_ID_PNG,
+                            _ => ffmpeg_next::ffi::AVCodecID::AV_CODEC_ID_MJPEG,
+                        };
+                        (*par).codec_tag = match picture.mime_type.as_str() {
+                            "image/png" => u32::from_be_bytes(*b"png "),
+                            _ => u32::from_be_bytes(*b"jpeg"),
+                        };
+                        (*par).width = picture.width as i32;
+                        (*par).height = picture.height as i32;
+
+                        (*cover_stream).disposition =
+                            ffmpeg_next::ffi::AV_DISPOSITION_ATTACHED_PIC as i32;
+
+                        // the ID3 muxer maps these back to the APIC description and picture type
+                        let key = std::ffi::CString::new("title").unwrap();
+                        let val = std::ffi::CString::new(picture.description.as_str()).unwrap();
+                        av_dict_set(&mut (*cover_stream).metadata, key.as_ptr(), val.as_ptr(), 0);
+                        let key = std::ffi::CString::new("comment").unwrap();
+                        let val = std::ffi::CString::new(picture.type_name()).unwrap();
+                        av_dict_set(&mut (*cover_stream).metadata, key.as_ptr(), val.as_ptr(), 0);
+
+                        picture_streams.push(((*cover_stream).index as usize, picture));
+                    }
+                }
+            }
+        }
+
+        let out_time_base = octx.stream(0).unwrap().time_base();
+
+        octx.set_metadata(metadata);
+        octx.write_header()?;
+
+        Ok(Self {
+            path,
+            octx,
+            encoder,
+            out_time_base,
+            picture_streams,
+            embeds_cover_art,
+        })
     }
 
-    fn send_eof_to_encoder(&mut self) {
-        self.encoder.send_eof().unwrap();
+    /// Closes the output and removes the partly written file, so it isn't taken for a converted one
+    fn discard(self) {
+        let path = self.path.clone();
+        drop(self);
+        let _ = fs::remove_file(path);
     }
 
-    fn receive_and_process_encoded_packets(&mut self, octx: &mut format::context::Output) {
+    /// Sends a frame to the encoder, or the end of the stream when there is none, and writes out
+    /// whatever packets it produced
+    fn encode(
+        &mut self,
+        frame: Option<&frame::Audio>,
+        in_time_base: ffmpeg_next::Rational,
+    ) -> Result<(), ffmpeg_next::Error> {
+        match frame {
+            Some(frame) => self.encoder.send_frame(frame)?,
+            None => self.encoder.send_eof()?,
+        }
+
         let mut encoded = ffmpeg_next::Packet::empty();
         while self.encoder.receive_packet(&mut encoded).is_ok() {
             encoded.set_stream(0);
-            encoded.rescale_ts(self.in_time_base, self.out_time_base);
-            encoded.write_interleaved(octx).unwrap();
+            encoded.rescale_ts(in_time_base, self.out_time_base);
+            encoded.write_interleaved(&mut self.octx)?;
         }
-    }
 
-    fn add_frame_to_filter(&mut self, frame: &ffmpeg_next::Frame) {
-        self.filter.get("in").unwrap().source().add(frame).unwrap();
+        Ok(())
     }
 
-    fn flush_filter(&mut self) {
-        self.filter.get("in").unwrap().source().flush().unwrap();
+    /// Flushes the encoder, then writes the pictures and the trailer, discarding the file if any
+    /// of it fails
+    fn finish(mut self, in_time_base: ffmpeg_next::Rational) -> Result<(), ffmpeg_next::Error> {
+        let result = self.write_end(in_time_base);
+        if result.is_err() {
+            self.discard();
+        }
+
+        result
     }
 
-    fn get_and_process_filtered_frames(&mut self, octx: &mut format::context::Output) {
-        let mut filtered = frame::Audio::empty();
-        while self
-            .filter
-            .get("out")
-            .unwrap()
-            .sink()
-            .frame(&mut filtered)
-            .is_ok()
-        {
-            self.send_frame_to_encoder(&filtered);
-            self.receive_and_process_encoded_packets(octx);
+    fn write_end(&mut self, in_time_base: ffmpeg_next::Rational) -> Result<(), ffmpeg_next::Error> {
+        self.encode(None, in_time_base)?;
 
+        for (index, picture) in &self.picture_streams {
             unsafe {
-                av_frame_unref(filtered.as_mut_ptr());
-            }
-        }
-    }
+                let cover_stream: *mut ffmpeg_next::ffi::AVStream =
+                    self.octx.stream(*index).unwrap().as_ptr().cast_mut();
 
-    fn send_packet_to_decoder(&mut self, packet: &ffmpeg_next::Packet) {
-        self.decoder.send_packet(packet).unwrap();
-    }
+                let data = av_malloc(picture.data.len()) as *mut u8;
+                if data.is_null() {
+                    return Err(ffmpeg_next::Error::Bug);
+                }
+                ptr::copy_nonoverlapping(picture.data.as_ptr(), data, picture.data.len());
 
-    fn send_eof_to_decoder(&mut self) {
-        self.decoder.send_eof().unwrap();
-    }
+                let pkt = &mut (*cover_stream).attached_pic;
+                av_init_packet(pkt);
+                pkt.data = data;
+                pkt.size = picture.data.len() as i32;
+                pkt.stream_index = (*cover_stream).index;
+                pkt.flags |= ffmpeg_next::ffi::AV_PKT_FLAG_KEY;
 
-    fn receive_and_process_decoded_frames(&mut self, octx: &mut format::context::Output) {
-        let mut decoded = frame::Audio::empty();
-        while self.decoder.receive_frame(&mut decoded).is_ok() {
-            let timestamp = decoded.timestamp();
-            decoded.set_pts(timestamp);
-            self.add_frame_to_filter(&decoded);
-            self.get_and_process_filtered_frames(octx);
+                av_write_frame(self.octx.as_mut_ptr(), pkt);
+            }
         }
+
+        self.octx.write_trailer()
     }
 }
 
-pub fn convert_file(
-    file: AudioFile,
-    out_codec: &AudioCodec,
-    out_sample_rate: &AudioSampleRate,
-    out_bitrate: usize,
-    out_directory: &Path,
-    out_container: &AudioContainer,
-    embed_cover_art: bool,
-    resize_cover_art: bool,
-    cover_art_size: u32,
-) -> Result<(), ffmpeg_next::Error> {
-    let mut output_path: String = out_directory.to_string_lossy().to_string() + "/";
-    if let Some(stem) = file.path.file_stem().unwrap().to_str() {
-        output_path += stem;
-    } else {
-        output_path += file.title.as_ref().unwrap();
+impl Transcoder {
+    fn new(
+        file: &AudioFile,
+        ictx: &mut format::context::Input,
+        options: &[OutputOptions],
+        cover_art_cache: &CoverArtCache,
+        max_errors: Option<usize>,
+    ) -> Result<Self, ffmpeg_next::Error> {
+        let (stream, decoder) = decoder(ictx)?;
+        let metadata = ictx.metadata();
+
+        // read a single time for every output, each encodes them to its own options
+        let source_pictures = match options
+            .iter()
+            .any(|o| o.embed_cover_art || o.sidecar_cover_art)
+        {
+            true => cover_art::read_pictures(&file.path).unwrap_or_default(),
+            false => Vec::new(),
+        };
+
+        let outputs: Vec<Result<Output, ffmpeg_next::Error>> = options
+            .iter()
+            .map(|o| {
+                Output::new(
+                    file,
+                    &metadata,
+                    &decoder,
+                    o,
+                    &source_pictures,
+                    cover_art_cache,
+                )
+            })
+            .collect();
+
+        let encoders: Vec<(usize, &codec::encoder::Audio)> = outputs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, o)| o.as_ref().ok().map(|o| (i, &o.encoder)))
+            .collect();
+        let sinks = encoders.iter().map(|(i, _)| *i).collect();
+        let filter = match encoders.is_empty() {
+            // every output already failed, there's nothing to decode for
+            true => Ok(filter::Graph::new()),
+            false => filter(&decoder, &encoders),
+        };
+        let filter = match filter {
+            Ok(filter) => filter,
+            Err(e) => {
+                outputs.into_iter().flatten().for_each(Output::discard);
+                return Err(e);
+            }
+        };
+
+        let in_time_base = decoder.time_base();
+
+        Ok(Self {
+            stream,
+            filter,
+            decoder,
+            in_time_base,
+            outputs,
+            sinks,
+            concealed_errors: 0,
+            max_errors,
+            decoded_samples: 0,
+        })
     }
 
-    output_path += match out_container {
-        AudioContainer::FLAC => ".flac",
-        AudioContainer::MP3 => ".mp3",
-        AudioContainer::M4A => ".m4a",
-        AudioContainer::OGG => ".ogg",
-        AudioContainer::OPUS => ".opus",
-    };
-
-    let mut ictx = format::input(&file.path)?;
-    let mut octx = format::output(&output_path)?;
-    let mut transcoder = transcoder(
-        &mut ictx,
-        &mut octx,
-        out_codec,
-        out_sample_rate,
-        out_bitrate,
-    )?;
-
-    let mut metadata = ictx.metadata().to_owned();
-    let mut cover_art: Vec<u8> = Vec::new();
-    if embed_cover_art {
-        if let Some(mut bytes) = file.ff_get_album_art().ok().flatten() {
-            cover_art = bytes.clone();
-            let reader = ImageReader::new(Cursor::new(bytes.clone()))
-                .with_guessed_format()
-                .unwrap();
-            let decoded = reader.decode().unwrap();
-
-            let mut width = decoded.width();
-            let mut height = decoded.height();
-
-            if resize_cover_art {
-                let resized = decoded.thumbnail(cover_art_size, cover_art_size);
-                bytes.clear();
-                resized
-                    .write_to(&mut Cursor::new(&mut cover_art), image::ImageFormat::Jpeg)
-                    .unwrap();
-
-                width = cover_art_size;
-                height = cover_art_size;
-            }
+    fn add_frame_to_filter(&mut self, frame: &ffmpeg_next::Frame) {
+        self.filter.get("in").unwrap().source().add(frame).unwrap();
+    }
 
-            let mimetype = image::guess_format(&cover_art)
-                .unwrap()
-                .to_mime_type()
-                .to_string();
+    fn flush_filter(&mut self) {
+        self.filter.get("in").unwrap().source().flush().unwrap();
+    }
 
-            if *out_codec == AudioCodec::FLAC
-                || *out_codec == AudioCodec::VORBIS
-                || *out_codec == AudioCodec::OPUS
-            {
-                let block = construct_flac_picture_block(3, &mimetype, "Front cover", &cover_art);
+    /// Drains every sink, frames for outputs that have failed are still pulled so asplit doesn't
+    /// keep buffering them
+    fn get_and_process_filtered_frames(&mut self) {
+        let mut filtered = frame::Audio::empty();
 
-                let cover_art_string = BASE64_STANDARD.encode(block);
-                metadata.set("METADATA_BLOCK_PICTURE", &cover_art_string);
-            } else if *out_codec == AudioCodec::AAC || *out_codec == AudioCodec::MP3 {
-                let cover_stream = unsafe { avformat_new_stream(octx.as_mut_ptr(), ptr::null()) };
-                if cover_stream.is_null() {
-                    return Err(ffmpeg_next::Error::Unknown);
+        for &i in &self.sinks {
+            let mut sink = self.filter.get(&sink_name(i)).unwrap();
+            while sink.sink().frame(&mut filtered).is_ok() {
+                if let Ok(output) = &mut self.outputs[i]
+                    && let Err(e) = output.encode(Some(&filtered), self.in_time_base)
+                    && let Ok(output) = std::mem::replace(&mut self.outputs[i], Err(e))
+                {
+                    output.discard();
                 }
 
                 unsafe {
-                    let par = (*cover_stream).codecpar;
-                    (*par).codec_type = ffmpeg_next::ffi::AVMediaType::AVMEDIA_TYPE_VIDEO;
-                    (*par).codec_id = match mimetype.as_str() {
-                        "image/png" => ffmpeg_next::ffi::AVCodecID::AV_CODEC_ID_PNG,
-                        _ => ffmpeg_next::ffi::AVCodecID::AV_CODEC_ID_MJPEG,
-                    };
-                    (*par).codec_tag = match mimetype.as_str() {
-                        "image/png" => u32::from_be_bytes(*b"png "),
-                        _ => u32::from_be_bytes(*b"jpeg"),
-                    };
-                    (*par).width = width as i32;
-                    (*par).height = height as i32;
-
-                    (*cover_stream).disposition =
-                        ffmpeg_next::ffi::AV_DISPOSITION_ATTACHED_PIC as i32;
-
-                    let key = std::ffi::CString::new("title").unwrap();
-                    let val = std::ffi::CString::new("Album Art").unwrap();
-                    av_dict_set(&mut (*cover_stream).metadata, key.as_ptr(), val.as_ptr(), 0);
-                    let key = std::ffi::CString::new("comment").unwrap();
-                    let val = std::ffi::CString::new("Cover (front)").unwrap();
-                    av_dict_set(&mut (*cover_stream).metadata, key.as_ptr(), val.as_ptr(), 0);
+                    av_frame_unref(filtered.as_mut_ptr());
                 }
             }
         }
     }
 
-    octx.set_metadata(metadata);
-    octx.write_header().unwrap();
+    /// Skips over the error when the policy still allows it, the bad packet's audio is dropped
+    fn conceal_error(&mut self, error: ffmpeg_next::Error) -> Result<(), ffmpeg_next::Error> {
+        if self
+            .max_errors
+            .is_some_and(|max| self.concealed_errors >= max)
+        {
+            return Err(error);
+        }
 
-    for (stream, mut packet) in ictx.packets() {
-        let i = stream.index();
+        self.concealed_errors += 1;
+        Ok(())
+    }
 
-        if i == transcoder.stream {
-            packet.rescale_ts(stream.time_base(), transcoder.in_time_base);
-            transcoder.send_packet_to_decoder(&packet);
-            transcoder.receive_and_process_decoded_frames(&mut octx);
-            continue;
+    fn send_packet_to_decoder(
+        &mut self,
+        packet: &ffmpeg_next::Packet,
+    ) -> Result<(), ffmpeg_next::Error> {
+        match self.decoder.send_packet(packet) {
+            Err(e) => self.conceal_error(e),
+            Ok(()) => Ok(()),
         }
     }
 
-    transcoder.send_eof_to_decoder();
-    transcoder.receive_and_process_decoded_frames(&mut octx);
+    fn send_eof_to_decoder(&mut self) -> Result<(), ffmpeg_next::Error> {
+        self.decoder.send_eof()
+    }
 
-    transcoder.flush_filter();
-    transcoder.get_and_process_filtered_frames(&mut octx);
+    fn receive_and_process_decoded_frames(&mut self) -> Result<(), ffmpeg_next::Error> {
+        let mut decoded = frame::Audio::empty();
+        loop {
+            match self.decoder.receive_frame(&mut decoded) {
+                Ok(()) => {
+                    self.decoded_samples += decoded.samples() as u64;
+                    let timestamp = decoded.timestamp();
+                    decoded.set_pts(timestamp);
+                    self.add_frame_to_filter(&decoded);
+                    self.get_and_process_filtered_frames();
+                }
+                Err(ffmpeg_next::Error::Other {
+                    errno: ffmpeg_next::error::EAGAIN,
+                })
+                | Err(ffmpeg_next::Error::Eof) => return Ok(()),
+                // some decoders only report a bad packet once its frame is received
+                Err(e) => return self.conceal_error(e),
+            }
+        }
+    }
 
-    transcoder.send_eof_to_encoder();
-    transcoder.receive_and_process_encoded_packets(&mut octx);
+    /// Decodes every packet of the audio stream and encodes it to each output
+    fn run(&mut self, ictx: &mut format::context::Input) -> Result<(), ffmpeg_next::Error> {
+        for (stream, mut packet) in ictx.packets() {
+            if stream.index() == self.stream {
+                packet.rescale_ts(stream.time_base(), self.in_time_base);
+                self.send_packet_to_decoder(&packet)?;
+                self.receive_and_process_decoded_frames()?;
+            }
+        }
 
-    if embed_cover_art {
-        if *out_codec == AudioCodec::AAC || *out_codec == AudioCodec::MP3 {
-            unsafe {
-                let cover_stream: *mut ffmpeg_next::ffi::AVStream =
-                    octx.stream(1).unwrap().as_ptr().cast_mut();
+        self.send_eof_to_decoder()?;
+        self.receive_and_process_decoded_frames()?;
 
-                let data = av_malloc(cover_art.len()) as *mut u8;
-                if data.is_null() {
-                    return Err(ffmpeg_next::Error::Bug);
-                }
-                ptr::copy_nonoverlapping(cover_art.as_ptr(), data, cover_art.len());
+        self.flush_filter();
+        self.get_and_process_filtered_frames();
 
-                let pkt = &mut (*cover_stream).attached_pic;
-                av_init_packet(pkt);
-                pkt.data = data;
-                pkt.size = cover_art.len() as i32;
-                pkt.stream_index = (*cover_stream).index;
-                pkt.flags |= ffmpeg_next::ffi::AV_PKT_FLAG_KEY;
+        Ok(())
+    }
 
-                let key = std::ffi::CString::new("title").unwrap();
-                let val = std::ffi::CString::new("Cover (front)").unwrap();
-                av_dict_set(&mut (*cover_stream).metadata, key.as_ptr(), val.as_ptr(), 0);
-                let key = std::ffi::CString::new("comment").unwrap();
-                let val = std::ffi::CString::new("Cover Art").unwrap();
-                av_dict_set(&mut (*cover_stream).metadata, key.as_ptr(), val.as_ptr(), 0);
+    /// Removes every output written so far, the conversion failed part way
+    fn discard(self) {
+        self.outputs.into_iter().flatten().for_each(Output::discard);
+    }
 
-                av_write_frame(octx.as_mut_ptr(), pkt);
-            }
+    fn finish(self) -> Converted {
+        let in_time_base = self.in_time_base;
+        let source_duration = match self.decoder.rate() {
+            0 => None,
+            rate => Some(self.decoded_samples as f64 / rate as f64),
+        };
+        let embedded_cover_art = self
+            .outputs
+            .iter()
+            .map(|o| o.as_ref().is_ok_and(|o| o.embeds_cover_art))
+            .collect();
+
+        Converted {
+            results: self
+                .outputs
+                .into_iter()
+                .map(|output| output.and_then(|o| o.finish(in_time_base)))
+                .collect(),
+            embedded_cover_art,
+            concealed_errors: self.concealed_errors,
+            source_duration,
         }
     }
-
-    octx.write_trailer().unwrap();
-
-    Ok(())
 }
 
-fn construct_flac_picture_block(
-    pic_type: u32,
-    mime: &str,
-    description: &str,
-    image_data: &[u8],
-) -> Vec<u8> {
-    let mut buf = Vec::new();
-
-    let _ = buf.write_u32::<BigEndian>(pic_type);
+/// Converts the file to every output at once, decoding it a single time. Up to `max_errors`
+/// decoding errors are skipped before every output fails, or any number of them when None
+pub fn convert_file(
+    file: &AudioFile,
+    outputs: &[OutputOptions],
+    cover_art_cache: &CoverArtCache,
+    max_errors: Option<usize>,
+) -> Converted {
+    match transcode(file, outputs, cover_art_cache, max_errors) {
+        Ok(converted) => converted,
+        Err(e) => Converted {
+            results: vec![Err(e); outputs.len()],
+            embedded_cover_art: vec![false; outputs.len()],
+            concealed_errors: 0,
+            source_duration: None,
+        },
+    }
+}
 
-    let _ = buf.write_u32::<BigEndian>(mime.len() as u32).unwrap();
-    let _ = buf.extend_from_slice(mime.as_bytes());
+fn transcode(
+    file: &AudioFile,
+    outputs: &[OutputOptions],
+    cover_art_cache: &CoverArtCache,
+    max_errors: Option<usize>,
+) -> Result<Converted, ffmpeg_next::Error> {
+    let mut ictx = format::input(&file.path)?;
+    let mut transcoder = Transcoder::new(file, &mut ictx, outputs, cover_art_cache, max_errors)?;
 
-    let _ = buf
-        .write_u32::<BigEndian>(description.len() as u32)
-        .unwrap();
-    let _ = buf.extend_from_slice(description.as_bytes());
+    if transcoder.sinks.is_empty() {
+        return Ok(transcoder.finish());
+    }
 
-    // unknown width, height, depth, colors
-    let _ = buf.write_u32::<BigEndian>(0);
-    let _ = buf.write_u32::<BigEndian>(0);
-    let _ = buf.write_u32::<BigEndian>(0);
-    let _ = buf.write_u32::<BigEndian>(0);
+    match transcoder.run(&mut ictx) {
+        Ok(()) => Ok(transcoder.finish()),
+        Err(e) => {
+            transcoder.discard();
+            Err(e)
+        }
+    }
+}
 
-    let _ = buf.write_u32::<BigEndian>(image_data.len() as u32).unwrap();
-    let _ = buf.extend_from_slice(image_data);
+/// Encodes the source's pictures per the cover art options, WebP is only kept for Vorbis comment
+/// pictures since MP4 and most ID3 readers accept nothing but JPEG or PNG
+fn prepare_pictures(
+    pictures: &[Picture],
+    options: &CoverArtOptions,
+    cache: &CoverArtCache,
+    out_codec: &AudioCodec,
+) -> Vec<Picture> {
+    let allow_webp = matches!(
+        out_codec,
+        AudioCodec::FLAC | AudioCodec::VORBIS | AudioCodec::OPUS
+    );
 
-    buf
+    pictures
+        .iter()
+        .filter_map(|p| cache.encoded(p, options, allow_webp))
+        .collect()
 }
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+use crate::app::{AppState, NO_ALBUM, NO_ARTIST, NO_TITLE};
+use crate::models::audio_file::{self, AlbumArtError, AudioFile};
+use crate::models::settings::Settings;
+use crate::tasks_manager::TasksManager;
+
+const THUMBNAIL_SIZE: u32 = 48;
+
+pub enum AlbumThumbnail {
+    Loading(mpsc::Receiver<Result<egui::ColorImage, AlbumArtError>>),
+    Loaded(egui::TextureHandle),
+    Missing,
+}
+
+#[derive(Hash, PartialEq, Eq, Clone)]
+struct AlbumKey {
+    album_artist: Option<String>,
+    album: Option<String>,
+    disc: Option<u32>,
+}
+
+impl AlbumKey {
+    fn new(file: &AudioFile) -> Self {
+        Self {
+            album_artist: file.album_artist.clone().or(file.artist.clone()),
+            album: file.album.clone(),
+            disc: file.disc_number(),
+        }
+    }
+
+    fn title(&self) -> String {
+        let mut title = format!(
+            "{} - {}",
+            self.album_artist.as_deref().unwrap_or(NO_ARTIST),
+            self.album.as_deref().unwrap_or(NO_ALBUM)
+        );
+        if let Some(disc) = self.disc {
+            title += &format!(" (disc {disc})");
+        }
+
+        title
+    }
+}
+
+/// Visible rows grouped by album artist, album and disc, in the order the albums first appear in
+/// the table
+fn group_albums(files: &[AudioFile], rows: &[usize]) -> Vec<(AlbumKey, Vec<usize>)> {
+    let mut albums: Vec<(AlbumKey, Vec<usize>)> = Vec::new();
+    let mut positions: HashMap<AlbumKey, usize> = HashMap::new();
+
+    for &i in rows {
+        let key = AlbumKey::new(&files[i]);
+        match positions.get(&key) {
+            Some(&pos) => albums[pos].1.push(i),
+            None => {
+                positions.insert(key.clone(), albums.len());
+                albums.push((key, vec![i]));
+            }
+        }
+    }
+
+    albums
+}
+
+/// Starts loading the album's cover the first time its header is on screen
+fn album_thumbnail(state: &mut AppState, file: usize, ui: &mut egui::Ui) {
+    let path: PathBuf = state.files[file].path.clone();
+    let size = egui::Vec2::splat(THUMBNAIL_SIZE as f32);
+
+    let thumbnail = state.album_thumbnails.entry(path).or_insert_with(|| {
+        AlbumThumbnail::Loading(
+            state.files[file].load_album_art(Some(THUMBNAIL_SIZE), &state.cover_art_cache),
+        )
+    });
+
+    if let AlbumThumbnail::Loading(rx) = thumbnail {
+        match rx.try_recv() {
+            Ok(Ok(image)) => {
+                let texture = ui.ctx().load_texture(
+                    format!("album_thumbnail_{file}"),
+                    image,
+                    egui::TextureOptions::LINEAR,
+                );
+                *thumbnail = AlbumThumbnail::Loaded(texture);
+            }
+            Ok(Err(_)) | Err(mpsc::TryRecvError::Disconnected) => {
+                *thumbnail = AlbumThumbnail::Missing
+            }
+            Err(mpsc::TryRecvError::Empty) => ui.ctx().request_repaint(),
+        }
+    }
+
+    match thumbnail {
+        AlbumThumbnail::Loaded(texture) => {
+            ui.add(
+                egui::Image::from_texture(&*texture)
+                    .fit_to_exact_size(size)
+                    .corner_radius(3),
+            );
+        }
+        _ => {
+            ui.allocate_space(size);
+        }
+    }
+}
+
+pub fn album_view(
+    state: &mut AppState,
+    tasks_manager: &mut TasksManager,
+    settings: &Settings,
+    ui: &mut egui::Ui,
+) {
+    let albums = group_albums(&state.files, &state.visible_rows);
+    let mut clicked_row: Option<usize> = None;
+
+    egui::ScrollArea::vertical()
+        .auto_shrink([false, true])
+        .show(ui, |ui| {
+            for (key, rows) in &albums {
+                let id = ui.make_persistent_id(("album", key.title()));
+
+                egui::collapsing_header::CollapsingState::load_with_default_open(
+                    ui.ctx(),
+                    id,
+                    false,
+                )
+                .show_header(ui, |ui| {
+                    let all_selected = rows.iter().all(|i| state.table_selections.contains(i));
+                    let mut selected = all_selected;
+                    if ui.checkbox(&mut selected, "").changed() {
+                        if selected {
+                            state.table_selections.extend(rows);
+                            state.first_selection.get_or_insert(rows[0]);
+                            state.last_selection = Some(rows[0]);
+                        } else {
+                            state.table_selections.retain(|i| !rows.contains(i));
+                            state.update_visible_rows();
+                        }
+                    }
+
+                    // covers are only loaded for albums that have been scrolled into view
+                    if ui.is_rect_visible(ui.max_rect()) {
+                        album_thumbnail(state, rows[0], ui);
+                    }
+
+                    let duration: f64 = rows
+                        .iter()
+                        .filter_map(|i| state.files[*i].stream.duration)
+                        .sum();
+                    ui.vertical(|ui| {
+                        ui.strong(key.title());
+                        ui.label(format!(
+                            "{} tracks, {}",
+                            rows.len(),
+                            audio_file::format_duration(duration)
+                        ));
+                    });
+
+                    if ui.button("Convert").clicked() {
+                        let files = rows.iter().map(|i| state.files[*i].clone()).collect();
+                        state.convert(files, settings, tasks_manager);
+                    }
+                })
+                .body(|ui| {
+                    for &i in rows {
+                        let file = &state.files[i];
+                        let mut text = String::new();
+                        if let Some(track) = file.track_number() {
+                            text += &format!("{track}. ");
+                        }
+                        text += file.title.as_deref().unwrap_or(NO_TITLE);
+                        if let Some(duration) = file.stream.duration {
+                            text += &format!("  ({})", audio_file::format_duration(duration));
+                        }
+
+                        let selected = state.table_selections.contains(&i);
+                        if ui.selectable_label(selected, text).clicked() {
+                            clicked_row = Some(i);
+                        }
+                    }
+                });
+            }
+        });
+
+    if let Some(i) = clicked_row {
+        let toggle = ui.input(|input| input.modifiers.command);
+        if toggle && state.table_selections.contains(&i) {
+            state.table_selections.remove(&i);
+        } else {
+            if !toggle {
+                state.table_selections.clear();
+            }
+            state.table_selections.insert(i);
+            state.first_selection = Some(i);
+            state.last_selection = Some(i);
+            state.cover_art_rx =
+                Some(state.files[i].load_album_art(Some(300), &state.cover_art_cache));
+        }
+        state.update_visible_rows();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(
+        album_artist: Option<&str>,
+        artist: &str,
+        album: &str,
+        disc: Option<&str>,
+    ) -> AudioFile {
+        AudioFile {
+            album_artist: album_artist.map(str::to_string),
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+            disc: disc.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn albums_are_grouped_by_album_artist_and_disc() {
+        let files = vec![
+            file(Some("Band"), "Band feat. Guest", "Live", Some("1/2")),
+            file(None, "Other", "Live", None),
+            file(None, "Band", "Live", Some("1")),
+            file(Some("Band"), "Band", "Live", Some("2/2")),
+        ];
+
+        let albums = group_albums(&files, &[0, 1, 2, 3]);
+        let groups: Vec<(String, Vec<usize>)> = albums
+            .into_iter()
+            .map(|(key, rows)| (key.title(), rows))
+            .collect();
+
+        assert_eq!(
+            groups,
+            vec![
+                ("Band - Live (disc 1)".to_string(), vec![0, 2]),
+                ("Other - Live".to_string(), vec![1]),
+                ("Band - Live (disc 2)".to_string(), vec![3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_visible_rows_are_grouped() {
+        let files = vec![
+            file(None, "Band", "Live", None),
+            file(None, "Band", "Live", None),
+        ];
+
+        let albums = group_albums(&files, &[1]);
+
+        assert_eq!(albums.len(), 1);
+        assert_eq!(albums[0].1, vec![1]);
+    }
+}
@@ -0,0 +1,55 @@
+use crate::models::settings::FileColumn;
+
+/// Shown columns in table order with buttons to move them, followed by the hidden columns
+pub fn columns_menu(columns: &mut Vec<FileColumn>, ui: &mut egui::Ui) {
+    let mut hide = None;
+    let mut move_up = None;
+
+    for (i, column) in columns.iter().enumerate() {
+        ui.horizontal(|ui| {
+            let mut shown = true;
+            // the table needs at least one column to click on
+            if ui
+                .add_enabled(
+                    columns.len() > 1,
+                    egui::Checkbox::new(&mut shown, column.label()),
+                )
+                .changed()
+            {
+                hide = Some(i);
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui
+                    .add_enabled(i + 1 < columns.len(), egui::Button::new("⏷"))
+                    .clicked()
+                {
+                    move_up = Some(i + 1);
+                }
+                if ui.add_enabled(i > 0, egui::Button::new("⏶")).clicked() {
+                    move_up = Some(i);
+                }
+            });
+        });
+    }
+
+    if let Some(i) = hide {
+        columns.remove(i);
+    }
+    if let Some(i) = move_up {
+        columns.swap(i - 1, i);
+    }
+
+    ui.separator();
+
+    for column in FileColumn::ALL {
+        if columns.contains(&column) {
+            continue;
+        }
+
+        let mut shown = false;
+        if ui.checkbox(&mut shown, column.label()).changed() {
+            columns.push(column);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::app::AppState;
+use crate::models::settings::SourceAction;
+use crate::tasks_manager::TasksManager;
+
+/// Asks before converting files whose sources would be moved or deleted afterwards
+pub fn confirm_conversion_window(
+    state: &mut AppState,
+    tasks_manager: &mut TasksManager,
+    ctx: &egui::Context,
+) {
+    let Some((files, settings)) = state.pending_conversion.take() else {
+        return;
+    };
+
+    let mut open = true;
+    let mut cancelled = false;
+    let mut confirmed = false;
+
+    egui::Window::new("Convert and handle sources?")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            // tasks use the first target that doesn't keep the source
+            let handling = settings
+                .target_settings()
+                .into_iter()
+                .map(|(_, s)| s)
+                .find(|s| s.out_source_action != SourceAction::Keep);
+            let action = match handling {
+                Some(s) if s.out_source_action == SourceAction::MoveToFolder => {
+                    format!("moved to {}", s.out_source_folder)
+                }
+                Some(s) if s.out_source_action == SourceAction::Trash => {
+                    "moved to the trash".to_string()
+                }
+                _ => "deleted permanently".to_string(),
+            };
+
+            ui.label(format!(
+                "After converting, the sources of the {} file(s) will be {action}",
+                files.len()
+            ));
+
+            ui.label("Sources are only touched once every output has been converted and checked");
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if ui.button("Convert").clicked() {
+                    confirmed = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    cancelled = true;
+                }
+            });
+        });
+
+    if confirmed {
+        state.queue_conversion(files, &settings, tasks_manager);
+    } else if open && !cancelled {
+        state.pending_conversion = Some((files, settings));
+    }
+}
+
+/// Explains why a conversion wasn't queued
+pub fn conversion_error_window(state: &mut AppState, ctx: &egui::Context) {
+    let Some(error) = &state.conversion_error else {
+        return;
+    };
+
+    let mut open = true;
+    let mut dismissed = false;
+
+    egui::Window::new("Can't convert")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+            ui.separator();
+
+            if ui.button("OK").clicked() {
+                dismissed = true;
+            }
+        });
+
+    if !open || dismissed {
+        state.conversion_error = None;
+    }
+}
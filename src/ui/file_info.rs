@@ -3,6 +3,22 @@ use std::sync::mpsc;
 use egui::{Sense, Vec2};
 
 use crate::app::AppState;
+use crate::models::audio_file;
+
+fn window_id() -> egui::Id {
+    egui::Id::new("file_info_popup")
+}
+
+/// Expands the popup, which otherwise starts collapsed
+pub fn open_file_info_popup(ctx: &egui::Context) {
+    let mut collapsing = egui::collapsing_header::CollapsingState::load_with_default_open(
+        ctx,
+        window_id().with("collapsing"),
+        false,
+    );
+    collapsing.set_open(true);
+    collapsing.store(ctx);
+}
 
 pub fn file_info_popup(state: &mut AppState, ctx: &egui::Context) {
     use egui::Align2;
@@ -14,6 +30,7 @@ pub fn file_info_popup(state: &mut AppState, ctx: &egui::Context) {
         .clone();
 
     egui::Window::new("File information")
+        .id(window_id())
         .min_width(300.0)
         .max_width(300.0)
         .anchor(Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
@@ -53,6 +70,53 @@ pub fn file_info_popup(state: &mut AppState, ctx: &egui::Context) {
                     ui.end_row();
                 });
 
+            ui.separator();
+            ui.strong("Technical");
+            egui::Grid::new("technical_file_info")
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    let stream = &file.stream;
+
+                    ui.label("Codec:");
+                    if stream.lossless {
+                        ui.label(format!("{} (lossless)", stream.codec));
+                    } else {
+                        ui.label(&stream.codec);
+                    }
+                    ui.end_row();
+
+                    ui.label("Sample rate:");
+                    ui.label(audio_file::format_sample_rate(stream.sample_rate));
+                    ui.end_row();
+
+                    if let Some(bit_depth) = stream.bit_depth {
+                        ui.label("Bit depth:");
+                        ui.label(format!("{bit_depth}-bit"));
+                        ui.end_row();
+                    }
+
+                    ui.label("Channels:");
+                    ui.label(audio_file::format_channels(stream.channels));
+                    ui.end_row();
+
+                    if let Some(bit_rate) = stream.bit_rate {
+                        ui.label("Bitrate:");
+                        ui.label(audio_file::format_bit_rate(bit_rate));
+                        ui.end_row();
+                    }
+
+                    if let Some(duration) = stream.duration {
+                        ui.label("Duration:");
+                        ui.label(audio_file::format_duration(duration));
+                        ui.end_row();
+                    }
+
+                    ui.label("File size:");
+                    ui.label(audio_file::format_file_size(stream.file_size));
+                    ui.end_row();
+                });
+
             ui.separator();
 
             if let Some(rx) = &state.cover_art_rx {
@@ -87,7 +151,7 @@ pub fn file_info_popup(state: &mut AppState, ctx: &egui::Context) {
                 );
 
                 if response.clicked() {
-                    state.lg_cover_art_rx = Some(file.load_album_art(None));
+                    state.lg_cover_art_rx = Some(file.load_album_art(None, &state.cover_art_cache));
                     state.showing_lg_art = true;
                 }
             }
@@ -0,0 +1,61 @@
+use std::collections::BTreeSet;
+
+use crate::app::AppState;
+use crate::models::audio_file;
+
+const SAMPLE_RATE_THRESHOLDS: [u32; 3] = [44100, 48000, 96000];
+
+pub fn filter_bar(state: &mut AppState, ui: &mut egui::Ui) {
+    ui.horizontal(|ui| {
+        let filter = &mut state.filter;
+
+        ui.add(
+            egui::TextEdit::singleline(&mut filter.text)
+                .hint_text("🔍 Search tags and paths")
+                .desired_width(250.0),
+        );
+
+        egui::ComboBox::from_id_salt("filter_codec")
+            .selected_text(filter.codec.as_deref().unwrap_or("Any codec"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut filter.codec, None, "Any codec");
+
+                let codecs: BTreeSet<&String> =
+                    state.files.iter().map(|f| &f.stream.codec).collect();
+                for codec in codecs {
+                    ui.selectable_value(&mut filter.codec, Some(codec.clone()), codec);
+                }
+            });
+
+        egui::ComboBox::from_id_salt("filter_sample_rate")
+            .selected_text(match filter.sample_rate_above {
+                Some(rate) => format!("> {}", audio_file::format_sample_rate(rate)),
+                None => "Any sample rate".to_string(),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut filter.sample_rate_above, None, "Any sample rate");
+                for rate in SAMPLE_RATE_THRESHOLDS {
+                    ui.selectable_value(
+                        &mut filter.sample_rate_above,
+                        Some(rate),
+                        format!("> {}", audio_file::format_sample_rate(rate)),
+                    );
+                }
+            });
+
+        ui.checkbox(&mut filter.missing_cover, "Missing cover");
+        ui.checkbox(&mut filter.missing_tags, "Missing tags");
+
+        if filter.is_active() {
+            if ui.button("Clear filter").clicked() {
+                *filter = Default::default();
+            }
+
+            ui.label(format!(
+                "Showing {} of {} files",
+                state.visible_rows.len(),
+                state.files.len()
+            ));
+        }
+    });
+}
@@ -0,0 +1,42 @@
+use crate::app::AppState;
+
+pub fn import_report_window(state: &mut AppState, ctx: &egui::Context) {
+    use egui::Align2;
+
+    let mut open = true;
+
+    egui::Window::new("Import report")
+        .anchor(Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .collapsible(false)
+        .resizable(true)
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "{} file(s) could not be imported:",
+                state.import_errors.len()
+            ));
+            ui.separator();
+
+            egui::ScrollArea::both().max_height(300.0).show(ui, |ui| {
+                egui::Grid::new("import_errors")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (path, error) in &state.import_errors {
+                            ui.label(path.to_string_lossy());
+                            ui.label(error.to_string());
+                            ui.end_row();
+                        }
+                    });
+            });
+
+            ui.separator();
+            if ui.button("Close").clicked() {
+                state.showing_import_report = false;
+            }
+        });
+
+    if !open {
+        state.showing_import_report = false;
+    }
+}
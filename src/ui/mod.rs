@@ -1,4 +1,14 @@
 pub mod album_art_viewer;
+pub mod album_view;
+pub mod columns;
+pub mod confirm_conversion;
 pub mod file_info;
+pub mod filter_bar;
+pub mod import_report;
+pub mod playlists;
+pub mod presets;
+pub mod selection_settings;
 pub mod settings;
+pub mod sync;
+pub mod targets;
 pub mod task_queue;
@@ -0,0 +1,37 @@
+use crate::playlist::Playlist;
+
+/// The opened playlists, with the entries that point at nothing and a button to remove each
+pub fn playlists_menu(playlists: &mut Vec<Playlist>, ui: &mut egui::Ui) {
+    let mut remove = None;
+
+    for (i, playlist) in playlists.iter().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label(playlist.name())
+                .on_hover_text(playlist.path.to_string_lossy());
+            ui.label(format!("{} entries", playlist.entries.len()));
+
+            let missing = playlist.missing_entries();
+            if !missing.is_empty() {
+                let locations: Vec<&str> = missing.iter().map(|e| e.location.as_str()).collect();
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!("{} missing", missing.len()),
+                )
+                .on_hover_text(locations.join("\n"));
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        });
+    }
+
+    if let Some(i) = remove {
+        playlists.remove(i);
+    }
+
+    ui.separator();
+    ui.label("Playlists are written into the output directory along with the files they list");
+}
@@ -0,0 +1,137 @@
+use crate::app::AppState;
+use crate::models::preset::Preset;
+use crate::models::settings::Settings;
+
+/// Appends " (2)", " (3)"... until the name isn't used by another preset
+fn unique_name(settings: &Settings, name: &str) -> String {
+    let presets = Preset::all(settings);
+    let taken = |n: &str| presets.iter().any(|p| p.name == n);
+
+    let mut unique = name.to_string();
+    let mut i = 2;
+    while taken(&unique) {
+        unique = format!("{name} ({i})");
+        i += 1;
+    }
+
+    unique
+}
+
+/// Combo box applying a preset to the output settings, shows "Custom" once a setting is changed
+pub fn preset_selector(settings: &mut Settings, ui: &mut egui::Ui) {
+    let presets = Preset::all(settings);
+    let current = presets.iter().find(|p| p.matches(settings));
+
+    egui::ComboBox::from_id_salt("preset_selector")
+        .selected_text(current.map(|p| p.name.as_str()).unwrap_or("Custom"))
+        .show_ui(ui, |ui| {
+            for preset in &presets {
+                if ui
+                    .selectable_label(preset.matches(settings), &preset.name)
+                    .clicked()
+                {
+                    preset.apply(settings);
+                }
+            }
+        });
+}
+
+pub fn presets_section(settings: &mut Settings, state: &mut AppState, ui: &mut egui::Ui) {
+    ui.horizontal(|ui| {
+        ui.label("Preset");
+        preset_selector(settings, ui);
+    });
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut state.preset_name)
+                .hint_text("Preset name")
+                .desired_width(150.0),
+        );
+
+        let name = state.preset_name.trim().to_string();
+        let is_builtin = Preset::builtins().iter().any(|p| p.name == name);
+        let exists = settings.presets.iter().any(|p| p.name == name);
+        let label = if exists {
+            "Update preset"
+        } else {
+            "Save as preset"
+        };
+
+        if ui
+            .add_enabled(!name.is_empty() && !is_builtin, egui::Button::new(label))
+            .on_disabled_hover_text("Built-in presets can't be changed, pick another name")
+            .clicked()
+        {
+            let preset = Preset::from_settings(&name, settings);
+            match settings.presets.iter_mut().find(|p| p.name == name) {
+                Some(existing) => *existing = preset,
+                None => settings.presets.push(preset),
+            }
+            state.preset_name.clear();
+        }
+    });
+
+    let mut duplicate = None;
+    let mut export = None;
+    let mut delete = None;
+
+    let builtin_count = Preset::builtins().len();
+    egui::Grid::new("presets").num_columns(2).show(ui, |ui| {
+        for (i, preset) in Preset::all(settings).into_iter().enumerate() {
+            ui.label(&preset.name);
+            ui.horizontal(|ui| {
+                if ui.small_button("Duplicate").clicked() {
+                    duplicate = Some(preset.clone());
+                }
+                if ui.small_button("Export").clicked() {
+                    export = Some(preset.clone());
+                }
+                if i >= builtin_count && ui.small_button("Delete").clicked() {
+                    delete = Some(i - builtin_count);
+                }
+            });
+            ui.end_row();
+        }
+    });
+
+    if let Some(preset) = duplicate {
+        let name = unique_name(settings, &preset.name);
+        settings.presets.push(Preset { name, ..preset });
+    }
+
+    if let Some(i) = delete {
+        settings.presets.remove(i);
+    }
+
+    if let Some(preset) = export
+        && let Some(path) = rfd::FileDialog::new()
+            .add_filter("preset", &["ron"])
+            .set_file_name(format!("{}.ron", preset.name))
+            .save_file()
+    {
+        state.preset_error = preset
+            .save(&path)
+            .err()
+            .map(|e| format!("Couldn't export preset: {e}"));
+    }
+
+    if ui.button("Import preset...").clicked()
+        && let Some(path) = rfd::FileDialog::new()
+            .add_filter("preset", &["ron"])
+            .pick_file()
+    {
+        match Preset::load(&path) {
+            Ok(preset) => {
+                let name = unique_name(settings, &preset.name);
+                settings.presets.push(Preset { name, ..preset });
+                state.preset_error = None;
+            }
+            Err(e) => state.preset_error = Some(format!("Couldn't import preset: {e}")),
+        }
+    }
+
+    if let Some(error) = &state.preset_error {
+        ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+}
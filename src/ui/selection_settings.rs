@@ -0,0 +1,61 @@
+use crate::app::AppState;
+use crate::tasks_manager::TasksManager;
+use crate::ui::settings::output_settings_list;
+
+pub fn selection_settings_window(
+    state: &mut AppState,
+    tasks_manager: &mut TasksManager,
+    ctx: &egui::Context,
+) {
+    let Some(mut settings) = state.selection_settings.take() else {
+        return;
+    };
+
+    let mut open = true;
+    let mut cancelled = false;
+    let mut convert = false;
+
+    egui::Window::new("Convert selection")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "These settings only apply to the {} selected files",
+                state.table_selections.len()
+            ));
+            ui.separator();
+
+            egui::ScrollArea::vertical()
+                .max_height(ctx.content_rect().height() * 0.6)
+                .show(ui, |ui| {
+                    output_settings_list(&mut settings, state, ui);
+                });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        !state.table_selections.is_empty(),
+                        egui::Button::new("Convert selected"),
+                    )
+                    .clicked()
+                {
+                    convert = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    cancelled = true;
+                }
+            });
+        });
+
+    if convert {
+        // the targets' presets would replace the settings picked here
+        settings.out_multiple_targets = false;
+        let files = state.selected_files();
+        state.convert(files, &settings, tasks_manager);
+    } else if open && !cancelled {
+        state.selection_settings = Some(settings);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::app::AppState;
 use crate::models::audio_file::{AudioCodec, AudioContainer, AudioSampleRate};
-use crate::models::settings::{AppTheme, OutputGrouping, Settings};
+use crate::models::settings::{
+    AppTheme, CoverArtFormat, DecodeErrorPolicy, OutputGrouping, Settings, SourceAction,
+};
 
 pub fn settings_list(settings: &mut Settings, state: &AppState, ui: &mut egui::Ui) {
     egui::Grid::new("settings")
@@ -37,261 +39,487 @@ pub fn settings_list(settings: &mut Settings, state: &AppState, ui: &mut egui::U
             );
             ui.end_row();
 
+            let decode_errors_tooltip = "What to do when part of a source can't be decoded, skipped audio is left out of the output and counted in the results";
+            ui.label("Decoding errors")
+                .on_hover_text_at_pointer(decode_errors_tooltip);
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("decode_errors_combobox")
+                    .selected_text(settings.run_decode_errors.label())
+                    .show_ui(ui, |ui| {
+                        for policy in [
+                            DecodeErrorPolicy::Abort,
+                            DecodeErrorPolicy::Skip,
+                            DecodeErrorPolicy::SkipUpTo,
+                        ] {
+                            let label = policy.label();
+                            ui.selectable_value(&mut settings.run_decode_errors, policy, label);
+                        }
+                    })
+                    .response
+                    .on_hover_text_at_pointer(decode_errors_tooltip);
+
+                if settings.run_decode_errors == DecodeErrorPolicy::SkipUpTo {
+                    ui.add(
+                        egui::DragValue::new(&mut settings.run_max_decode_errors)
+                            .fixed_decimals(0)
+                            .speed(1.0)
+                            .range(1..=1000),
+                    );
+                }
+            });
+            ui.end_row();
+
             ui.separator();
             ui.separator();
             ui.end_row();
 
-            ui.heading("Output settings");
+            ui.heading("Import settings");
             ui.end_row();
 
-            ui.label("Audio codec");
-            egui::ComboBox::from_id_salt("output_codec_combobox")
-                .selected_text(match settings.out_codec {
-                    AudioCodec::FLAC => "FLAC",
-                    AudioCodec::MP3 => "MP3",
-                    AudioCodec::AAC => "AAC",
-                    AudioCodec::OPUS => "OPUS",
-                    AudioCodec::VORBIS => "VORBIS",
-                })
-                .show_ui(ui, |ui| {
-                    if ui
-                        .selectable_value(
-                            &mut settings.out_codec,
-                            AudioCodec::FLAC,
-                            "FLAC",
-                        )
-                        .clicked()
-                    {
-                        settings.out_container = AudioContainer::FLAC;
-                    }
-                    if ui
-                        .selectable_value(&mut settings.out_codec, AudioCodec::MP3, "MP3")
-                        .clicked()
-                    {
-                        settings.out_container = AudioContainer::MP3;
-                        if settings.out_sample_rate == AudioSampleRate::HiRes96 {
-                            settings.out_sample_rate = AudioSampleRate::Studio48;
-                        }
-                    }
-                    if ui
-                        .selectable_value(&mut settings.out_codec, AudioCodec::AAC, "AAC")
-                        .clicked()
-                    {
-                        settings.out_container = AudioContainer::M4A;
-                        if settings.out_sample_rate == AudioSampleRate::HiRes96 {
-                            settings.out_sample_rate = AudioSampleRate::Studio48;
-                        }
-                    }
-                    if ui
-                        .selectable_value(
-                            &mut settings.out_codec,
-                            AudioCodec::OPUS,
-                            "OPUS",
-                        )
-                        .clicked()
-                    {
-                        settings.out_container = AudioContainer::OGG;
-                        if settings.out_sample_rate == AudioSampleRate::HiRes96 {
-                            settings.out_sample_rate = AudioSampleRate::Studio48;
-                        }
-                    }
-                    if ui
-                        .selectable_value(
-                            &mut settings.out_codec,
-                            AudioCodec::VORBIS,
-                            "VORBIS",
-                        )
-                        .clicked()
-                    {
-                        settings.out_container = AudioContainer::OGG;
-                    };
-                });
+            ui.label("Include subfolders")
+                .on_hover_text_at_pointer("Scan folders inside opened or dropped folders");
+            ui.checkbox(&mut settings.import_recursive, "");
             ui.end_row();
 
-            ui.label("Audio container");
-            egui::ComboBox::from_id_salt("output_container_combobox")
-                .selected_text(match settings.out_container {
-                    AudioContainer::FLAC => ".flac",
-                    AudioContainer::MP3 => ".mp3",
-                    AudioContainer::M4A => ".m4a",
-                    AudioContainer::OGG => ".ogg",
-                    AudioContainer::OPUS => ".opus",
-                })
-                .show_ui(ui, |ui| match settings.out_codec {
-                    AudioCodec::FLAC => {
-                        ui.selectable_value(
-                            &mut settings.out_container,
-                            AudioContainer::FLAC,
-                            ".flac",
-                        );
-                    }
-                    AudioCodec::MP3 => {
-                        ui.selectable_value(
-                            &mut settings.out_container,
-                            AudioContainer::MP3,
-                            ".mp3",
-                        );
-                    }
-                    AudioCodec::AAC => {
-                        ui.selectable_value(
-                            &mut settings.out_container,
-                            AudioContainer::M4A,
-                            ".m4a",
-                        );
-                    }
-                    AudioCodec::OPUS => {
-                        ui.selectable_value(
-                            &mut settings.out_container,
-                            AudioContainer::OPUS,
-                            ".opus",
-                        );
-                        ui.selectable_value(
-                            &mut settings.out_container,
-                            AudioContainer::OGG,
-                            ".ogg",
-                        );
-                    }
-                    AudioCodec::VORBIS => {
-                        ui.selectable_value(
-                            &mut settings.out_container,
-                            AudioContainer::OGG,
-                            ".ogg",
-                        );
-                    }
-                });
+            ui.label("Skip hidden files")
+                .on_hover_text_at_pointer("Skip files and folders starting with a '.'");
+            ui.checkbox(&mut settings.import_skip_hidden, "");
             ui.end_row();
 
-            ui.label("Sample rate");
-            egui::ComboBox::from_id_salt("output_samplerate_combobox")
-                .selected_text(match settings.out_sample_rate {
-                    AudioSampleRate::CD44 => "CD (44.1kHz)",
-                    AudioSampleRate::Studio48 => "Studio (48kHz)",
-                    AudioSampleRate::HiRes96 => "HiRes (96kHz)",
-                })
-                .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut settings.out_sample_rate, AudioSampleRate::CD44, "CD (44.1kHz)");
-                    ui.selectable_value(&mut settings.out_sample_rate, AudioSampleRate::Studio48, "Studio (48kHz)");
-
-                    if settings.out_codec == AudioCodec::FLAC {
-                        ui.selectable_value(&mut settings.out_sample_rate, AudioSampleRate::HiRes96, "HiRes (96kHz)");
-                    }
-                });
+            let probe_unknown_tooltip = "Open files with unrecognised extensions to check if they contain audio\n - slower for folders with many non-audio files";
+            ui.label("Detect unknown files").on_hover_text_at_pointer(probe_unknown_tooltip);
+            ui.checkbox(&mut settings.import_probe_unknown, "").on_hover_text_at_pointer(probe_unknown_tooltip);
             ui.end_row();
 
-            ui.label("Bitrate");
-            ui.add(
-                egui::DragValue::new(&mut settings.out_bitrate)
-                    .fixed_decimals(0)
-                    .speed(1000.0),
+            let extensions_width = ui.available_width().min(240.0);
+
+            ui.label("Extra extensions")
+                .on_hover_text_at_pointer("Always try to import these extensions, e.g. 'mp4, mkv'");
+            ui.add_sized(
+                [extensions_width, ui.text_style_height(&egui::TextStyle::Body)],
+                egui::TextEdit::singleline(&mut settings.import_extra_extensions).hint_text("mp4, mkv"),
             );
             ui.end_row();
 
-            let text_width = ui.available_width().min(240.0);
-
-            ui.label("Output Directory");
-            ui.horizontal(|ui| {
-                if ui
-                    .add_sized(
-                        [text_width, ui.text_style_height(&egui::TextStyle::Body)],
-                        egui::TextEdit::singleline(&mut settings.out_directory),
-                    )
-                    .double_clicked()
-                    || ui.button("🗁").clicked()
-                {
-                    if let Some(dir) = rfd::FileDialog::new().pick_folder() {
-                        settings.out_directory = dir.to_str().unwrap().to_string();
-                    }
-                }
-            });
+            ui.label("Excluded extensions")
+                .on_hover_text_at_pointer("Never import these extensions, e.g. 'wav'");
+            ui.add_sized(
+                [extensions_width, ui.text_style_height(&egui::TextStyle::Body)],
+                egui::TextEdit::singleline(&mut settings.import_excluded_extensions).hint_text("wav"),
+            );
             ui.end_row();
 
-            ui.label("Group by...")
-                .on_hover_text_at_pointer("Group output files in a folder");
-            egui::ComboBox::from_id_salt("output_grouping_combobox")
-                .selected_text(match settings.out_grouping {
-                    OutputGrouping::NoGrouping => "No Grouping",
-                    OutputGrouping::Copy => "Copy from source",
-                    OutputGrouping::ArtistAlbum => "Artist - Album",
-                    OutputGrouping::Album => "Album",
-                    OutputGrouping::Artist => "Artist",
-                })
-                .show_ui(ui, |ui| {
-                    ui.selectable_value(
-                        &mut settings.out_grouping,
-                        OutputGrouping::NoGrouping,
-                        "No Grouping",
-                    )
-                    .on_hover_text_at_pointer(
-                        "Group output files in a folder:\n - No grouping/folders",
-                    );
-                    ui.selectable_value(
-                        &mut settings.out_grouping,
-                        OutputGrouping::Copy,
-                        "Copy from source",
-                    )
-                    .on_hover_text_at_pointer(
-                        "Group output files in a folder:\n - Parent folder from original files",
-                    );
-                    ui.selectable_value(
-                        &mut settings.out_grouping,
-                        OutputGrouping::ArtistAlbum,
-                        "Artist - Album",
-                    )
-                    .on_hover_text_at_pointer({
-                        let first_file = state.files.first();
-                        let artist = first_file.and_then(|f| f.artist.as_deref()).unwrap_or("Artist");
-                        let album = first_file.and_then(|f| f.album.as_deref()).unwrap_or("Album");
-                        format!("Group output files in a folder:\n - Create a folder name '{artist} - {album}'")
-                    });
-                    ui.selectable_value(
-                        &mut settings.out_grouping,
-                        OutputGrouping::Album,
-                        "Album",
-                    )
-                    .on_hover_text_at_pointer({
-                        let first_file = state.files.first();
-                        let album = first_file.and_then(|f| f.album.as_deref()).unwrap_or("Album");
-                        format!("Group output files in a folder:\n - Create a folder name '{album}'")
-                    });
-                    ui.selectable_value(
-                        &mut settings.out_grouping,
-                        OutputGrouping::Artist,
-                        "Artist",
-                    ).on_hover_text_at_pointer({
-                        let first_file = state.files.first();
-                        let artist = first_file.and_then(|f| f.artist.as_deref()).unwrap_or("Artist");
-                        format!("Group output files in a folder:\n - Create a folder name '{artist}'")
-                    });
-                }).response.on_hover_text_at_pointer("Group output files in a folder");
+            ui.separator();
+            ui.separator();
             ui.end_row();
 
-            let cover_art_tooltip = "Toggle embedding cover art as a Vorbis metadata block\n - depending on the source file, it may inflate file size";
-            ui.label("Embed cover art").on_hover_text_at_pointer(cover_art_tooltip);
-            ui.checkbox(&mut settings.out_embed_art, "").on_hover_text_at_pointer(cover_art_tooltip);
+            ui.heading("Output settings");
             ui.end_row();
 
-            let resize_cover_art_tooltip = "Resize the embedded cover art and compress to Jpeg, reduces final file size";
-            ui.add_enabled_ui(settings.out_embed_art, |ui| {
-                ui.label("Resize cover art?").on_hover_text_at_pointer(resize_cover_art_tooltip);
-            });
-            ui.add_enabled_ui(settings.out_embed_art, |ui| {
-                ui.checkbox(&mut settings.out_enable_cover_art_resize, "").on_hover_text_at_pointer(resize_cover_art_tooltip);
-            });
-            ui.end_row();
+            output_settings_rows(settings, state, ui);
+        });
+}
 
-            ui.add_enabled_ui(settings.out_enable_cover_art_resize && settings.out_embed_art, |ui| {
-                ui.label("Cover art resolution");
+/// Output settings on their own, for settings that only apply to some files
+pub fn output_settings_list(settings: &mut Settings, state: &AppState, ui: &mut egui::Ui) {
+    egui::Grid::new("output_settings")
+        .num_columns(2)
+        .show(ui, |ui| {
+            output_settings_rows(settings, state, ui);
+        });
+}
+
+fn output_settings_rows(settings: &mut Settings, state: &AppState, ui: &mut egui::Ui) {
+    ui.label("Audio codec");
+    egui::ComboBox::from_id_salt("output_codec_combobox")
+        .selected_text(match settings.out_codec {
+            AudioCodec::FLAC => "FLAC",
+            AudioCodec::MP3 => "MP3",
+            AudioCodec::AAC => "AAC",
+            AudioCodec::OPUS => "OPUS",
+            AudioCodec::VORBIS => "VORBIS",
+        })
+        .show_ui(ui, |ui| {
+            if ui
+                .selectable_value(&mut settings.out_codec, AudioCodec::FLAC, "FLAC")
+                .clicked()
+            {
+                settings.out_container = AudioContainer::FLAC;
+            }
+            if ui
+                .selectable_value(&mut settings.out_codec, AudioCodec::MP3, "MP3")
+                .clicked()
+            {
+                settings.out_container = AudioContainer::MP3;
+                if settings.out_sample_rate == AudioSampleRate::HiRes96 {
+                    settings.out_sample_rate = AudioSampleRate::Studio48;
+                }
+            }
+            if ui
+                .selectable_value(&mut settings.out_codec, AudioCodec::AAC, "AAC")
+                .clicked()
+            {
+                settings.out_container = AudioContainer::M4A;
+                if settings.out_sample_rate == AudioSampleRate::HiRes96 {
+                    settings.out_sample_rate = AudioSampleRate::Studio48;
+                }
+            }
+            if ui
+                .selectable_value(&mut settings.out_codec, AudioCodec::OPUS, "OPUS")
+                .clicked()
+            {
+                settings.out_container = AudioContainer::OGG;
+                if settings.out_sample_rate == AudioSampleRate::HiRes96 {
+                    settings.out_sample_rate = AudioSampleRate::Studio48;
+                }
+            }
+            if ui
+                .selectable_value(&mut settings.out_codec, AudioCodec::VORBIS, "VORBIS")
+                .clicked()
+            {
+                settings.out_container = AudioContainer::OGG;
+            };
+        });
+    ui.end_row();
+
+    ui.label("Audio container");
+    egui::ComboBox::from_id_salt("output_container_combobox")
+        .selected_text(match settings.out_container {
+            AudioContainer::FLAC => ".flac",
+            AudioContainer::MP3 => ".mp3",
+            AudioContainer::M4A => ".m4a",
+            AudioContainer::OGG => ".ogg",
+            AudioContainer::OPUS => ".opus",
+        })
+        .show_ui(ui, |ui| match settings.out_codec {
+            AudioCodec::FLAC => {
+                ui.selectable_value(&mut settings.out_container, AudioContainer::FLAC, ".flac");
+            }
+            AudioCodec::MP3 => {
+                ui.selectable_value(&mut settings.out_container, AudioContainer::MP3, ".mp3");
+            }
+            AudioCodec::AAC => {
+                ui.selectable_value(&mut settings.out_container, AudioContainer::M4A, ".m4a");
+            }
+            AudioCodec::OPUS => {
+                ui.selectable_value(&mut settings.out_container, AudioContainer::OPUS, ".opus");
+                ui.selectable_value(&mut settings.out_container, AudioContainer::OGG, ".ogg");
+            }
+            AudioCodec::VORBIS => {
+                ui.selectable_value(&mut settings.out_container, AudioContainer::OGG, ".ogg");
+            }
+        });
+    ui.end_row();
+
+    ui.label("Sample rate");
+    egui::ComboBox::from_id_salt("output_samplerate_combobox")
+        .selected_text(match settings.out_sample_rate {
+            AudioSampleRate::CD44 => "CD (44.1kHz)",
+            AudioSampleRate::Studio48 => "Studio (48kHz)",
+            AudioSampleRate::HiRes96 => "HiRes (96kHz)",
+        })
+        .show_ui(ui, |ui| {
+            ui.selectable_value(
+                &mut settings.out_sample_rate,
+                AudioSampleRate::CD44,
+                "CD (44.1kHz)",
+            );
+            ui.selectable_value(
+                &mut settings.out_sample_rate,
+                AudioSampleRate::Studio48,
+                "Studio (48kHz)",
+            );
+
+            if settings.out_codec == AudioCodec::FLAC {
+                ui.selectable_value(
+                    &mut settings.out_sample_rate,
+                    AudioSampleRate::HiRes96,
+                    "HiRes (96kHz)",
+                );
+            }
+        });
+    ui.end_row();
+
+    ui.label("Bitrate");
+    ui.add(
+        egui::DragValue::new(&mut settings.out_bitrate)
+            .fixed_decimals(0)
+            .speed(1000.0),
+    );
+    ui.end_row();
+
+    let text_width = ui.available_width().min(240.0);
+
+    ui.label("Output Directory");
+    ui.horizontal(|ui| {
+        if ui
+            .add_sized(
+                [text_width, ui.text_style_height(&egui::TextStyle::Body)],
+                egui::TextEdit::singleline(&mut settings.out_directory),
+            )
+            .double_clicked()
+            || ui.button("🗁").clicked()
+        {
+            if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                settings.out_directory = dir.to_str().unwrap().to_string();
+            }
+        }
+    });
+    ui.end_row();
+
+    ui.label("Group by...")
+        .on_hover_text_at_pointer("Group output files in a folder");
+    egui::ComboBox::from_id_salt("output_grouping_combobox")
+        .selected_text(match settings.out_grouping {
+            OutputGrouping::NoGrouping => "No Grouping",
+            OutputGrouping::Copy => "Copy from source",
+            OutputGrouping::ArtistAlbum => "Artist - Album",
+            OutputGrouping::Album => "Album",
+            OutputGrouping::Artist => "Artist",
+        })
+        .show_ui(ui, |ui| {
+            ui.selectable_value(
+                &mut settings.out_grouping,
+                OutputGrouping::NoGrouping,
+                "No Grouping",
+            )
+            .on_hover_text_at_pointer("Group output files in a folder:\n - No grouping/folders");
+            ui.selectable_value(
+                &mut settings.out_grouping,
+                OutputGrouping::Copy,
+                "Copy from source",
+            )
+            .on_hover_text_at_pointer(
+                "Group output files in a folder:\n - Parent folder from original files",
+            );
+            ui.selectable_value(
+                &mut settings.out_grouping,
+                OutputGrouping::ArtistAlbum,
+                "Artist - Album",
+            )
+            .on_hover_text_at_pointer({
+                let first_file = state.files.first();
+                let artist = first_file
+                    .and_then(|f| f.artist.as_deref())
+                    .unwrap_or("Artist");
+                let album = first_file
+                    .and_then(|f| f.album.as_deref())
+                    .unwrap_or("Album");
+                format!(
+                    "Group output files in a folder:\n - Create a folder name '{artist} - {album}'"
+                )
             });
-            ui.add_enabled_ui(settings.out_enable_cover_art_resize && settings.out_embed_art, |ui| {
-                ui.horizontal(|ui| {
-                    ui.add(
-                        egui::DragValue::new(&mut settings.out_cover_art_resolution)
-                            .fixed_decimals(0)
-                            .speed(10.0)
-                    );
-                    ui.label("px");
+            ui.selectable_value(&mut settings.out_grouping, OutputGrouping::Album, "Album")
+                .on_hover_text_at_pointer({
+                    let first_file = state.files.first();
+                    let album = first_file
+                        .and_then(|f| f.album.as_deref())
+                        .unwrap_or("Album");
+                    format!("Group output files in a folder:\n - Create a folder name '{album}'")
                 });
+            ui.selectable_value(&mut settings.out_grouping, OutputGrouping::Artist, "Artist")
+                .on_hover_text_at_pointer({
+                    let first_file = state.files.first();
+                    let artist = first_file
+                        .and_then(|f| f.artist.as_deref())
+                        .unwrap_or("Artist");
+                    format!("Group output files in a folder:\n - Create a folder name '{artist}'")
+                });
+        })
+        .response
+        .on_hover_text_at_pointer("Group output files in a folder");
+    ui.end_row();
+
+    let cover_art_tooltip = "Toggle embedding cover art as a Vorbis metadata block\n - depending on the source file, it may inflate file size";
+    ui.label("Embed cover art")
+        .on_hover_text_at_pointer(cover_art_tooltip);
+    ui.checkbox(&mut settings.out_embed_art, "")
+        .on_hover_text_at_pointer(cover_art_tooltip);
+    ui.end_row();
+
+    let sidecar_art_tooltip = "Write the cover art as 'cover.jpg' into each output folder\n - replaced when the album's cover changes\n - not written without grouping, as every album shares the output folder";
+    ui.label("Save cover art file")
+        .on_hover_text_at_pointer(sidecar_art_tooltip);
+    ui.checkbox(&mut settings.out_sidecar_art, "")
+        .on_hover_text_at_pointer(sidecar_art_tooltip);
+    ui.end_row();
+
+    let uses_cover_art = settings.out_embed_art || settings.out_sidecar_art;
+
+    let cover_art_format_tooltip = "Image format of the cover art\n - WebP is only embedded in FLAC, Ogg and Opus files, JPEG is used otherwise";
+    ui.add_enabled_ui(uses_cover_art, |ui| {
+        ui.label("Cover art format")
+            .on_hover_text_at_pointer(cover_art_format_tooltip);
+    });
+    ui.add_enabled_ui(uses_cover_art, |ui| {
+        egui::ComboBox::from_id_salt("cover_art_format_combobox")
+            .selected_text(match settings.out_cover_art_format {
+                CoverArtFormat::Keep => "Keep original",
+                CoverArtFormat::JPEG => "JPEG",
+                CoverArtFormat::PNG => "PNG",
+                CoverArtFormat::WEBP => "WebP",
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(
+                    &mut settings.out_cover_art_format,
+                    CoverArtFormat::Keep,
+                    "Keep original",
+                );
+                ui.selectable_value(
+                    &mut settings.out_cover_art_format,
+                    CoverArtFormat::JPEG,
+                    "JPEG",
+                );
+                ui.selectable_value(
+                    &mut settings.out_cover_art_format,
+                    CoverArtFormat::PNG,
+                    "PNG",
+                );
+                ui.selectable_value(
+                    &mut settings.out_cover_art_format,
+                    CoverArtFormat::WEBP,
+                    "WebP",
+                );
+            })
+            .response
+            .on_hover_text_at_pointer(cover_art_format_tooltip);
+    });
+    ui.end_row();
+
+    ui.add_enabled_ui(uses_cover_art, |ui| {
+        ui.label("JPEG quality");
+    });
+    ui.add_enabled_ui(uses_cover_art, |ui| {
+        ui.add(egui::Slider::new(
+            &mut settings.out_cover_art_jpeg_quality,
+            30..=100,
+        ));
+    });
+    ui.end_row();
+
+    let resize_cover_art_tooltip = "Resize the cover art, reduces final file size";
+    ui.add_enabled_ui(uses_cover_art, |ui| {
+        ui.label("Resize cover art?")
+            .on_hover_text_at_pointer(resize_cover_art_tooltip);
+    });
+    ui.add_enabled_ui(uses_cover_art, |ui| {
+        ui.checkbox(&mut settings.out_enable_cover_art_resize, "")
+            .on_hover_text_at_pointer(resize_cover_art_tooltip);
+    });
+    ui.end_row();
+
+    ui.add_enabled_ui(
+        settings.out_enable_cover_art_resize && uses_cover_art,
+        |ui| {
+            ui.label("Cover art resolution");
+        },
+    );
+    ui.add_enabled_ui(
+        settings.out_enable_cover_art_resize && uses_cover_art,
+        |ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut settings.out_cover_art_resolution)
+                        .fixed_decimals(0)
+                        .speed(10.0),
+                );
+                ui.label("px");
             });
-            ui.end_row();
+        },
+    );
+    ui.end_row();
+
+    let resize_enabled = settings.out_enable_cover_art_resize && uses_cover_art;
+    ui.add_enabled_ui(resize_enabled, |ui| {
+        ui.label("Only downscale").on_hover_text_at_pointer(
+            "Leave cover art that is already smaller than the resolution as is",
+        );
+    });
+    ui.add_enabled_ui(resize_enabled, |ui| {
+        ui.checkbox(&mut settings.out_cover_art_only_downscale, "");
+    });
+    ui.end_row();
+
+    ui.add_enabled_ui(resize_enabled, |ui| {
+        ui.label("Keep aspect ratio").on_hover_text_at_pointer(
+            "Fit the cover art within the resolution instead of stretching it to a square",
+        );
+    });
+    ui.add_enabled_ui(resize_enabled, |ui| {
+        ui.checkbox(&mut settings.out_cover_art_keep_aspect_ratio, "");
+    });
+    ui.end_row();
+
+    let max_size_tooltip = "Lower the JPEG quality until the cover art fits within this size";
+    ui.add_enabled_ui(uses_cover_art, |ui| {
+        ui.label("Limit cover art size?")
+            .on_hover_text_at_pointer(max_size_tooltip);
+    });
+    ui.add_enabled_ui(uses_cover_art, |ui| {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut settings.out_enable_cover_art_max_size, "")
+                .on_hover_text_at_pointer(max_size_tooltip);
+            ui.add_enabled(
+                settings.out_enable_cover_art_max_size,
+                egui::DragValue::new(&mut settings.out_cover_art_max_size_kb)
+                    .fixed_decimals(0)
+                    .speed(10.0)
+                    .range(10..=10000),
+            );
+            ui.label("KB");
+        });
+    });
+    ui.end_row();
+
+    let preserve_times_tooltip = "Give outputs the source's modification and access times instead of the time they were converted\n - when syncing a library, folders also get the library folder's modification time";
+    ui.label("Keep source times")
+        .on_hover_text_at_pointer(preserve_times_tooltip);
+    ui.checkbox(&mut settings.out_preserve_times, "")
+        .on_hover_text_at_pointer(preserve_times_tooltip);
+    ui.end_row();
+
+    let preserve_permissions_tooltip =
+        "Give outputs the source's Unix permissions, their owner can always write to them";
+    ui.label("Keep source permissions")
+        .on_hover_text_at_pointer(preserve_permissions_tooltip);
+    ui.checkbox(&mut settings.out_preserve_permissions, "")
+        .on_hover_text_at_pointer(preserve_permissions_tooltip);
+    ui.end_row();
+
+    let source_action_tooltip = "What to do with each source once it has been converted to every target and the outputs checked";
+    ui.label("Source afterwards")
+        .on_hover_text_at_pointer(source_action_tooltip);
+    egui::ComboBox::from_id_salt("source_action_combobox")
+        .selected_text(settings.out_source_action.label())
+        .show_ui(ui, |ui| {
+            for action in [
+                SourceAction::Keep,
+                SourceAction::MoveToFolder,
+                SourceAction::Trash,
+                SourceAction::Delete,
+            ] {
+                let label = action.label();
+                ui.selectable_value(&mut settings.out_source_action, action, label);
+            }
+        })
+        .response
+        .on_hover_text_at_pointer(source_action_tooltip);
+    ui.end_row();
+
+    if settings.out_source_action == SourceAction::MoveToFolder {
+        ui.label("Move sources to").on_hover_text_at_pointer(
+            "Sources keep their path relative to the folder they were opened from",
+        );
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut settings.out_source_folder).desired_width(150.0),
+            );
+            if ui.button("🗁").clicked()
+                && let Some(dir) = rfd::FileDialog::new().pick_folder()
+            {
+                settings.out_source_folder = dir.to_string_lossy().to_string();
+            }
         });
+        ui.end_row();
+    }
 }
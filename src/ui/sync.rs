@@ -0,0 +1,233 @@
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+use crate::app::AppState;
+use crate::models::preset::Preset;
+use crate::models::settings::{Settings, SourceAction};
+use crate::scanner::ScanOptions;
+use crate::sync::{self, SyncPlan};
+use crate::tasks_manager::TasksManager;
+
+pub enum SyncState {
+    Idle,
+    Comparing(mpsc::Receiver<SyncPlan>),
+    Planned(SyncPlan),
+    Started {
+        queued: usize,
+        errors: Vec<(PathBuf, String)>,
+    },
+}
+
+fn path_list(id: &str, title: String, paths: Vec<String>, ui: &mut egui::Ui) {
+    egui::CollapsingHeader::new(title)
+        .id_salt(id)
+        .enabled(!paths.is_empty())
+        .show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .id_salt(id)
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    for path in paths {
+                        ui.label(path);
+                    }
+                });
+        });
+}
+
+fn plan_summary(plan: &SyncPlan, delete_removed: bool, ui: &mut egui::Ui) {
+    let relative = |path: &PathBuf| {
+        path.strip_prefix(&plan.source_root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    };
+
+    path_list(
+        "sync_added",
+        format!("{} to add", plan.added.len()),
+        plan.added.iter().map(|f| relative(&f.path)).collect(),
+        ui,
+    );
+    path_list(
+        "sync_updated",
+        format!("{} to update", plan.updated.len()),
+        plan.updated.iter().map(|f| relative(&f.path)).collect(),
+        ui,
+    );
+    let removed = if delete_removed {
+        format!("{} to remove", plan.removed.len())
+    } else {
+        format!("{} no longer in the library, kept", plan.removed.len())
+    };
+    path_list(
+        "sync_removed",
+        removed,
+        plan.removed
+            .iter()
+            .map(|(source, _)| relative(source))
+            .collect(),
+        ui,
+    );
+    ui.label(format!("{} up to date", plan.up_to_date));
+    path_list(
+        "sync_playlists",
+        format!("{} playlist(s) to rewrite", plan.playlists.len()),
+        plan.playlists.iter().map(|p| relative(&p.path)).collect(),
+        ui,
+    );
+
+    if !plan.errors.is_empty() {
+        path_list(
+            "sync_errors",
+            format!("{} couldn't be read", plan.errors.len()),
+            plan.errors
+                .iter()
+                .map(|(path, e)| format!("{}: {e}", relative(path)))
+                .collect(),
+            ui,
+        );
+    }
+}
+
+pub fn sync_window(
+    state: &mut AppState,
+    settings: &mut Settings,
+    tasks_manager: &mut TasksManager,
+    ctx: &egui::Context,
+) {
+    if !state.showing_sync {
+        return;
+    }
+
+    if let SyncState::Comparing(rx) = &state.sync {
+        match rx.try_recv() {
+            Ok(plan) => state.sync = SyncState::Planned(plan),
+            Err(mpsc::TryRecvError::Disconnected) => state.sync = SyncState::Idle,
+            Err(mpsc::TryRecvError::Empty) => ctx.request_repaint(),
+        }
+    }
+
+    let mut open = state.showing_sync;
+    let mut run = false;
+
+    egui::Window::new("Sync library")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(true)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            ui.label("Mirrors a library into the output directory, only converting new and changed files");
+            ui.separator();
+
+            let comparing = matches!(state.sync, SyncState::Comparing(_));
+            ui.add_enabled_ui(!comparing, |ui| {
+                egui::Grid::new("sync_settings").num_columns(2).show(ui, |ui| {
+                    ui.label("Library");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut settings.sync_source)
+                                .desired_width(200.0),
+                        );
+                        if ui.button("🗁").clicked()
+                            && let Some(dir) = rfd::FileDialog::new().pick_folder()
+                        {
+                            settings.sync_source = dir.to_string_lossy().to_string();
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Mirror");
+                    ui.label(&settings.out_directory);
+                    ui.end_row();
+
+                    ui.label("Preset");
+                    ui.label(Preset::matching(settings).map(|p| p.name).unwrap_or("Custom".to_string()))
+                        .on_hover_text("The current output settings, files keep the library's folders instead of being grouped");
+                    ui.end_row();
+                });
+
+                ui.checkbox(
+                    &mut settings.sync_delete_removed,
+                    "Delete converted files whose source was removed",
+                );
+            });
+
+            ui.separator();
+            match &state.sync {
+                SyncState::Idle => {}
+                SyncState::Comparing(_) => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Comparing the library with the mirror...");
+                    });
+                }
+                SyncState::Planned(plan) => {
+                    plan_summary(plan, settings.sync_delete_removed, ui);
+                    if plan.is_empty() {
+                        ui.label("The mirror is up to date");
+                    }
+                }
+                SyncState::Started { queued, errors } => {
+                    ui.label(format!("Queued {queued} file(s) for conversion"));
+                    for (path, e) in errors {
+                        ui.colored_label(
+                            ui.visuals().error_fg_color,
+                            format!("Couldn't delete {}: {e}", path.display()),
+                        );
+                    }
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        !comparing && !settings.sync_source.trim().is_empty(),
+                        egui::Button::new("Compare"),
+                    )
+                    .clicked()
+                {
+                    // the targets' output directories aren't mirrored, and the library is never
+                    // moved or deleted
+                    let mut sync_settings = settings.clone();
+                    sync_settings.out_multiple_targets = false;
+                    sync_settings.out_source_action = SourceAction::Keep;
+
+                    state.sync = SyncState::Comparing(sync::plan(
+                        PathBuf::from(settings.sync_source.trim()),
+                        sync_settings,
+                        // the whole library is mirrored whatever the import settings
+                        ScanOptions {
+                            recursive: true,
+                            ..ScanOptions::from_settings(settings)
+                        },
+                        state.scan_cache.clone(),
+                    ));
+                }
+
+                let can_run = matches!(&state.sync, SyncState::Planned(plan) if !plan.is_empty());
+                if ui.add_enabled(can_run, egui::Button::new("Run sync")).clicked() {
+                    run = true;
+                }
+            });
+        });
+
+    if run && let SyncState::Planned(plan) = std::mem::replace(&mut state.sync, SyncState::Idle) {
+        let queued = plan.added.len() + plan.updated.len();
+        let errors = plan
+            .run(settings.sync_delete_removed, tasks_manager)
+            .into_iter()
+            .map(|(path, e)| (path, e.to_string()))
+            .collect();
+
+        state.sync = SyncState::Started { queued, errors };
+        if queued > 0 {
+            state.is_transcoding = true;
+        }
+    }
+
+    if !open {
+        state.showing_sync = false;
+        state.sync = SyncState::Idle;
+    }
+}
@@ -0,0 +1,52 @@
+use crate::models::preset::Preset;
+use crate::models::settings::{OutputTarget, Settings};
+
+pub fn targets_section(settings: &mut Settings, ui: &mut egui::Ui) {
+    ui.checkbox(&mut settings.out_multiple_targets, "Convert to multiple targets")
+        .on_hover_text_at_pointer(
+            "Convert every file once per target, each with its own preset and output directory\n - replaces the output settings above",
+        );
+
+    if !settings.out_multiple_targets {
+        return;
+    }
+
+    let presets = Preset::all(settings);
+    let mut remove = None;
+
+    for (i, target) in settings.out_targets.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt(("target_preset", i))
+                .selected_text(&target.preset)
+                .show_ui(ui, |ui| {
+                    for preset in &presets {
+                        ui.selectable_value(&mut target.preset, preset.name.clone(), &preset.name);
+                    }
+                });
+
+            let path_edit =
+                ui.add(egui::TextEdit::singleline(&mut target.out_directory).desired_width(150.0));
+            let browse = ui.button("🗁");
+            if path_edit.double_clicked() || browse.clicked() {
+                if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                    target.out_directory = dir.to_string_lossy().to_string();
+                }
+            }
+
+            if ui.button("🗑").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+
+    if let Some(i) = remove {
+        settings.out_targets.remove(i);
+    }
+
+    if ui.button("Add target").clicked() {
+        settings.out_targets.push(OutputTarget {
+            preset: presets[0].name.clone(),
+            out_directory: settings.out_directory.clone(),
+        });
+    }
+}
@@ -1,4 +1,6 @@
-use crate::tasks_manager::TasksManager;
+use std::collections::BTreeMap;
+
+use crate::tasks_manager::{PlaylistResult, TaskResult, TasksManager};
 
 pub fn task_queue_window(tasks_manager: &mut TasksManager, ctx: &egui::Context) {
     use egui::Align2;
@@ -9,10 +11,16 @@ pub fn task_queue_window(tasks_manager: &mut TasksManager, ctx: &egui::Context)
         .resizable(false)
         .title_bar(false)
         .show(ctx, |ui| {
+            let running = !tasks_manager.queue.is_empty() || !tasks_manager.active_tasks.is_empty();
             ui.horizontal(|ui| {
                 ui.heading("Queue");
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    ui.spinner();
+                    if running {
+                        ui.spinner();
+                    } else if ui.button("Clear").clicked() {
+                        tasks_manager.results.clear();
+                        tasks_manager.playlist_results.clear();
+                    }
                 })
             });
             ui.label(format!(
@@ -40,5 +48,133 @@ pub fn task_queue_window(tasks_manager: &mut TasksManager, ctx: &egui::Context)
                     ));
                 });
             }
+
+            if !tasks_manager.results.is_empty() {
+                ui.separator();
+                results_summary(&tasks_manager.results, ui);
+            }
+            if !tasks_manager.playlist_results.is_empty() {
+                ui.separator();
+                playlists_summary(&tasks_manager.playlist_results, ui);
+            }
+        });
+}
+
+/// Completed and failed counts for each output target, with the reason each file failed and the
+/// files converted despite decoding errors
+fn results_summary(results: &[TaskResult], ui: &mut egui::Ui) {
+    let mut targets: BTreeMap<Option<&str>, Vec<&TaskResult>> = BTreeMap::new();
+    for result in results {
+        targets
+            .entry(result.target.as_deref())
+            .or_default()
+            .push(result);
+    }
+
+    let source_errors: Vec<&TaskResult> = results
+        .iter()
+        .filter(|r| r.source_error.is_some())
+        .collect();
+
+    for (target, results) in targets {
+        let failed: Vec<&TaskResult> = results
+            .iter()
+            .copied()
+            .filter(|r| r.error.is_some())
+            .collect();
+        let skipped = results.iter().filter(|r| r.skipped).count();
+        let concealed: Vec<&TaskResult> = results
+            .iter()
+            .copied()
+            .filter(|r| r.error.is_none() && r.concealed_errors > 0)
+            .collect();
+
+        let mut summary = format!(
+            "{}: {} converted, {} failed",
+            target.unwrap_or("Output"),
+            results.len() - failed.len() - skipped,
+            failed.len()
+        );
+        if skipped > 0 {
+            summary += &format!(", {skipped} up to date");
+        }
+        if !concealed.is_empty() {
+            summary += &format!(", {} with skipped errors", concealed.len());
+        }
+
+        if failed.is_empty() && concealed.is_empty() {
+            ui.label(summary);
+            continue;
+        }
+
+        egui::CollapsingHeader::new(summary)
+            .id_salt(("task_results", target))
+            .show(ui, |ui| {
+                for result in failed {
+                    ui.label(format!(
+                        "{}: {}",
+                        result.path.to_string_lossy(),
+                        result.error.as_deref().unwrap_or_default()
+                    ));
+                }
+                for result in concealed {
+                    ui.label(format!(
+                        "{}: skipped {} decoding error(s)",
+                        result.path.to_string_lossy(),
+                        result.concealed_errors
+                    ));
+                }
+            });
+    }
+
+    if !source_errors.is_empty() {
+        egui::CollapsingHeader::new(format!(
+            "{} source(s) not moved or deleted",
+            source_errors.len()
+        ))
+        .id_salt("source_errors")
+        .show(ui, |ui| {
+            for result in source_errors {
+                ui.label(format!(
+                    "{}: {}",
+                    result.path.to_string_lossy(),
+                    result.source_error.as_deref().unwrap_or_default()
+                ));
+            }
+        });
+    }
+}
+
+/// Every written playlist, with the entries that were left out of it
+fn playlists_summary(results: &[PlaylistResult], ui: &mut egui::Ui) {
+    for (i, result) in results.iter().enumerate() {
+        let name = result.output.to_string_lossy();
+        let name = match &result.target {
+            Some(target) => format!("{target}: {name}"),
+            None => name.to_string(),
+        };
+
+        if let Some(e) = &result.error {
+            ui.colored_label(
+                ui.visuals().error_fg_color,
+                format!("{name} couldn't be written: {e}"),
+            );
+            continue;
+        }
+        if result.unresolved.is_empty() {
+            ui.label(format!("{name} written"));
+            continue;
+        }
+
+        egui::CollapsingHeader::new(format!(
+            "{name} written, {} entries left out",
+            result.unresolved.len()
+        ))
+        .id_salt(("playlist_results", i))
+        .show(ui, |ui| {
+            for entry in &result.unresolved {
+                ui.label(entry);
+            }
         });
+    }
 }
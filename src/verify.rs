@@ -0,0 +1,400 @@
+use std::path::{Path, PathBuf};
+
+use ffmpeg_next::ffi::{AVMD5, av_free, av_md5_alloc, av_md5_final, av_md5_init, av_md5_update};
+use ffmpeg_next::format::Sample;
+use ffmpeg_next::{codec, format, frame, media};
+
+use crate::models::audio_file::AudioFile;
+use crate::worker_pool::WorkerPool;
+
+/// Outputs may be a little longer or shorter than the source from the encoder's delay and padding
+const DURATION_TOLERANCE: f64 = 0.25;
+
+/// What decoding every packet of a file's audio stream found
+#[derive(Clone, Debug)]
+pub struct Decoded {
+    /// Seconds of audio the decoder produced
+    pub duration: f64,
+    /// Packets or frames the decoder rejected
+    pub errors: usize,
+    /// Packets the demuxer found cut short or damaged
+    pub truncated: usize,
+    /// Samples the FLAC STREAMINFO promises that couldn't be decoded
+    pub missing_samples: u64,
+    /// Whether the decoded audio matches the MD5 in the FLAC STREAMINFO, when the encoder stored
+    /// one
+    pub md5_matches: Option<bool>,
+}
+
+impl Decoded {
+    pub fn is_intact(&self) -> bool {
+        self.errors == 0
+            && self.truncated == 0
+            && self.missing_samples == 0
+            && self.md5_matches != Some(false)
+    }
+
+    /// Shown in the table, e.g. "OK, MD5 matches" or "MD5 mismatch, 3 decoding error(s)"
+    pub fn summary(&self) -> String {
+        if self.is_intact() {
+            return match self.md5_matches {
+                Some(true) => "OK, MD5 matches".to_string(),
+                _ => "OK".to_string(),
+            };
+        }
+
+        let mut problems = Vec::new();
+        if self.md5_matches == Some(false) {
+            problems.push("MD5 mismatch".to_string());
+        }
+        if self.errors > 0 {
+            problems.push(format!("{} decoding error(s)", self.errors));
+        }
+        if self.truncated > 0 {
+            problems.push(format!("{} truncated frame(s)", self.truncated));
+        }
+        if self.missing_samples > 0 {
+            problems.push(format!("{} sample(s) missing", self.missing_samples));
+        }
+
+        problems.join(", ")
+    }
+}
+
+/// FFmpeg's MD5, so checking FLAC files doesn't need another crate
+struct Md5(*mut AVMD5);
+
+impl Md5 {
+    fn new() -> Option<Self> {
+        let context = unsafe { av_md5_alloc() };
+        if context.is_null() {
+            return None;
+        }
+
+        unsafe { av_md5_init(context) };
+        Some(Self(context))
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        unsafe { av_md5_update(self.0, data.as_ptr(), data.len() as _) };
+    }
+
+    fn finish(self) -> [u8; 16] {
+        let mut digest = [0; 16];
+        unsafe { av_md5_final(self.0, digest.as_mut_ptr()) };
+        digest
+    }
+}
+
+impl Drop for Md5 {
+    fn drop(&mut self) {
+        unsafe { av_free(self.0.cast()) };
+    }
+}
+
+/// The sample size, total samples and MD5 from a FLAC stream's STREAMINFO, when it has an MD5
+fn flac_stream_info(parameters: &codec::Parameters) -> Option<(u32, u64, [u8; 16])> {
+    if parameters.id() != codec::Id::FLAC {
+        return None;
+    }
+
+    let extradata = unsafe {
+        let par = &*parameters.as_ptr();
+        if par.extradata.is_null() {
+            return None;
+        }
+        std::slice::from_raw_parts(par.extradata, par.extradata_size.max(0) as usize)
+    };
+    // some containers keep the "fLaC" marker and the block header before STREAMINFO
+    let info = match extradata.strip_prefix(b"fLaC") {
+        Some(rest) => rest.get(4..)?,
+        None => extradata,
+    };
+    let info = info.get(..34)?;
+
+    // 20 bits of sample rate, 3 of channels, 5 of sample size and 36 of total samples
+    let fields = u64::from_be_bytes(info[10..18].try_into().unwrap());
+    let bits = ((fields >> 36) & 0x1f) as u32 + 1;
+    let total_samples = fields & 0xf_ffff_ffff;
+    let md5: [u8; 16] = info[18..34].try_into().unwrap();
+
+    // encoders that can't seek back to the header leave it zeroed
+    (md5 != [0; 16]).then_some((bits, total_samples, md5))
+}
+
+/// Adds the frame to the MD5 the way FLAC computes it, as little-endian integers of the stream's
+/// sample size interleaved by channel. The decoder gives samples left-justified in 16 or 32 bits,
+/// returns false for any other format
+fn update_md5(md5: &mut Md5, decoded: &frame::Audio, bits: u32) -> bool {
+    let width = match decoded.format() {
+        Sample::I16(_) => 2,
+        Sample::I32(_) => 4,
+        _ => return false,
+    };
+    if bits > width * 8 {
+        return false;
+    }
+
+    let shift = width * 8 - bits;
+    let bytes = bits.div_ceil(8) as usize;
+    let channels = decoded.channels() as usize;
+    let mut buffer = Vec::with_capacity(decoded.samples() * channels * bytes);
+
+    for i in 0..decoded.samples() {
+        for channel in 0..channels {
+            let (plane, index) = match decoded.is_planar() {
+                true => (channel, i),
+                false => (0, i * channels + channel),
+            };
+
+            // linesize is only set for the first plane of audio, so the planes are read directly
+            let value = unsafe {
+                let data = (*decoded.as_ptr()).data[plane];
+                match width {
+                    2 => data.cast::<i16>().add(index).read_unaligned() as i32,
+                    _ => data.cast::<i32>().add(index).read_unaligned(),
+                }
+            } >> shift;
+            buffer.extend_from_slice(&value.to_le_bytes()[..bytes]);
+        }
+    }
+
+    md5.update(&buffer);
+    true
+}
+
+/// Receives every frame the decoder has ready, counting their samples
+fn receive_frames(
+    decoder: &mut codec::decoder::Audio,
+    md5: &mut Option<(Md5, u32)>,
+    samples: &mut u64,
+    errors: &mut usize,
+) {
+    let mut decoded = frame::Audio::empty();
+    loop {
+        match decoder.receive_frame(&mut decoded) {
+            Ok(()) => {
+                *samples += decoded.samples() as u64;
+                if let Some((hash, bits)) = md5
+                    && !update_md5(hash, &decoded, *bits)
+                {
+                    *md5 = None;
+                }
+            }
+            Err(ffmpeg_next::Error::Other {
+                errno: ffmpeg_next::error::EAGAIN,
+            })
+            | Err(ffmpeg_next::Error::Eof) => break,
+            Err(_) => {
+                *errors += 1;
+                break;
+            }
+        }
+    }
+}
+
+/// Decodes the whole audio stream without encoding it anywhere, checking FLAC's MD5 along the way
+pub fn decode(path: &Path) -> Result<Decoded, ffmpeg_next::Error> {
+    let mut ictx = format::input(path)?;
+    let input = ictx
+        .streams()
+        .best(media::Type::Audio)
+        .ok_or(ffmpeg_next::Error::StreamNotFound)?;
+    let stream = input.index();
+    let stream_info = flac_stream_info(&input.parameters());
+    let context = codec::context::Context::from_parameters(input.parameters())?;
+    let mut decoder = context.decoder().audio()?;
+
+    let mut md5 = stream_info.and_then(|(bits, _, _)| Md5::new().map(|md5| (md5, bits)));
+    let mut samples = 0;
+    let mut errors = 0;
+    let mut truncated = 0;
+
+    for (s, packet) in ictx.packets() {
+        if s.index() != stream {
+            continue;
+        }
+
+        if packet.is_corrupt() {
+            truncated += 1;
+        }
+        if decoder.send_packet(&packet).is_err() {
+            errors += 1;
+        }
+        receive_frames(&mut decoder, &mut md5, &mut samples, &mut errors);
+    }
+
+    let _ = decoder.send_eof();
+    receive_frames(&mut decoder, &mut md5, &mut samples, &mut errors);
+
+    let (missing_samples, md5_matches) = match stream_info {
+        Some((_, total_samples, expected)) => (
+            // a total of 0 means the encoder didn't know it
+            total_samples.saturating_sub(samples),
+            md5.map(|(md5, _)| md5.finish() == expected),
+        ),
+        None => (0, None),
+    };
+
+    Ok(Decoded {
+        duration: match decoder.rate() {
+            0 => 0.0,
+            rate => samples as f64 / rate as f64,
+        },
+        errors,
+        truncated,
+        missing_samples,
+        md5_matches,
+    })
+}
+
+/// Fails when the output is longer or shorter than the source by more than the encoder accounts
+/// for, passes when the source's length isn't known
+fn check_duration(output: f64, source: Option<f64>) -> Result<(), String> {
+    match source {
+        Some(expected) if (output - expected).abs() > DURATION_TOLERANCE => Err(format!(
+            "The output lasts {output:.2}s, the source {expected:.2}s"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Re-opens a converted file and decodes all of it, checking it's as long as the source and has
+/// the source's tags, and its cover art when one was embedded. The length is compared with the
+/// audio decoded from the source while converting it, as the container's duration is only an
+/// estimate for e.g. VBR MP3 without a header, and isn't checked when there's none
+pub fn verify_output(
+    output: &Path,
+    source: &AudioFile,
+    source_duration: Option<f64>,
+    expect_cover: bool,
+) -> Result<(), String> {
+    let converted = AudioFile::new(output.to_path_buf())
+        .map_err(|e| format!("Couldn't open the output: {e}"))?;
+    let decoded = decode(output).map_err(|e| format!("Couldn't decode the output: {e}"))?;
+
+    if !decoded.is_intact() {
+        return Err(format!("The output is damaged: {}", decoded.summary()));
+    }
+
+    check_duration(decoded.duration, source_duration)?;
+
+    let tags = [
+        ("artist", &source.artist, &converted.artist),
+        (
+            "album artist",
+            &source.album_artist,
+            &converted.album_artist,
+        ),
+        ("album", &source.album, &converted.album),
+        ("title", &source.title, &converted.title),
+    ];
+    for (name, expected, written) in tags {
+        if expected.is_some() && written != expected {
+            return Err(format!(
+                "The {name} tag is missing or different in the output"
+            ));
+        }
+    }
+
+    if expect_cover && !converted.has_cover_art {
+        return Err("The cover art is missing from the output".to_string());
+    }
+
+    Ok(())
+}
+
+/// Decodes loaded files on a pool of worker threads to find damaged sources, sending each result
+/// back as soon as the file is done
+pub struct SourceVerifier {
+    pool: WorkerPool<(PathBuf, Result<Decoded, String>)>,
+}
+
+impl SourceVerifier {
+    pub fn start(paths: Vec<PathBuf>) -> Self {
+        Self {
+            pool: WorkerPool::start(
+                move |_| paths,
+                |path: PathBuf| {
+                    let result = decode(&path).map_err(|e| e.to_string());
+                    Some((path, result))
+                },
+            ),
+        }
+    }
+
+    /// Returns the files checked since the last call, called every frame
+    pub fn receive(&mut self) -> Vec<(PathBuf, Result<Decoded, String>)> {
+        self.pool.receive()
+    }
+
+    pub fn cancel(&self) {
+        self.pool.cancel();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pool.is_finished()
+    }
+
+    pub fn total(&self) -> usize {
+        self.pool.total()
+    }
+
+    pub fn checked(&self) -> usize {
+        self.pool.done()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(md5_matches: Option<bool>) -> Decoded {
+        Decoded {
+            duration: 180.0,
+            errors: 0,
+            truncated: 0,
+            missing_samples: 0,
+            md5_matches,
+        }
+    }
+
+    #[test]
+    fn intact_files_mention_a_matching_md5() {
+        assert_eq!(decoded(Some(true)).summary(), "OK, MD5 matches");
+        assert_eq!(decoded(None).summary(), "OK");
+        assert!(decoded(None).is_intact());
+    }
+
+    #[test]
+    fn every_problem_is_listed() {
+        let damaged = Decoded {
+            errors: 3,
+            missing_samples: 4096,
+            ..decoded(Some(false))
+        };
+
+        assert!(!damaged.is_intact());
+        assert_eq!(
+            damaged.summary(),
+            "MD5 mismatch, 3 decoding error(s), 4096 sample(s) missing"
+        );
+
+        let truncated = Decoded {
+            truncated: 1,
+            ..decoded(None)
+        };
+        assert!(!truncated.is_intact());
+        assert_eq!(truncated.summary(), "1 truncated frame(s)");
+    }
+
+    #[test]
+    fn outputs_may_differ_from_the_source_by_the_encoder_padding() {
+        assert!(check_duration(180.1, Some(180.0)).is_ok());
+        assert!(check_duration(12.0, None).is_ok());
+        assert_eq!(
+            check_duration(170.0, Some(180.0)),
+            Err("The output lasts 170.00s, the source 180.00s".to_string())
+        );
+    }
+}
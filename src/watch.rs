@@ -0,0 +1,403 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
+
+use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind, RenameMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::app::AudioConverterApp;
+use crate::cover_art_cache::CoverArtCache;
+use crate::models::audio_file::AudioFile;
+use crate::models::preset::Preset;
+use crate::models::settings::{Settings, SourceAction};
+use crate::scanner::{self, InputKind, ScanOptions};
+use crate::source_handling;
+use crate::tasks_manager::TasksManager;
+
+/// How long a file's size has to stay the same before it's considered written, for copies that
+/// don't report closing the file
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub const USAGE: &str =
+    "--watch <inbox> --preset <name> [--output <dir>] [--done <dir>] [--failed <dir>]";
+
+pub struct WatchOptions {
+    pub inbox: PathBuf,
+    pub preset: String,
+    /// Replaces the output directory from the saved settings
+    pub output: Option<String>,
+    /// Where sources are moved once converted, keeping their path relative to the inbox
+    pub done: Option<PathBuf>,
+    pub failed: Option<PathBuf>,
+}
+
+impl WatchOptions {
+    /// Parses the arguments following `--watch`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut args = args.iter();
+        let inbox = args.next().ok_or("Missing the folder to watch")?;
+
+        let mut options = WatchOptions {
+            inbox: PathBuf::from(inbox),
+            preset: String::new(),
+            output: None,
+            done: None,
+            failed: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or(format!("Missing the value of {arg}"))?
+                .clone();
+
+            match arg.as_str() {
+                "--preset" => options.preset = value,
+                "--output" => options.output = Some(value),
+                "--done" => options.done = Some(PathBuf::from(value)),
+                "--failed" => options.failed = Some(PathBuf::from(value)),
+                _ => return Err(format!("Unknown option {arg}")),
+            }
+        }
+
+        if options.preset.is_empty() {
+            return Err("Missing --preset".to_string());
+        }
+
+        Ok(options)
+    }
+
+    /// The folders the watch writes into, files written there by the watch itself aren't new rips.
+    /// They're created so they can be made absolute the way events report paths, however they
+    /// were written on the command line
+    fn written_folders(&self, settings: &Settings) -> Vec<PathBuf> {
+        let moved_to = (settings.out_source_action == SourceAction::MoveToFolder
+            && !settings.out_source_folder.trim().is_empty())
+        .then(|| PathBuf::from(settings.out_source_folder.trim()));
+
+        [
+            self.done.clone(),
+            self.failed.clone(),
+            Some(PathBuf::from(&settings.out_directory)),
+            moved_to,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|folder| {
+            let _ = fs::create_dir_all(&folder);
+            fs::canonicalize(&folder).unwrap_or(folder)
+        })
+        .collect()
+    }
+}
+
+fn is_ignored(path: &Path, written_folders: &[PathBuf]) -> bool {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    written_folders
+        .iter()
+        .any(|folder| path.starts_with(folder))
+}
+
+/// Files that are still being written, with their last seen size
+struct Pending {
+    size: u64,
+    changed: Instant,
+}
+
+fn add_pending(path: PathBuf, pending: &mut HashMap<PathBuf, Pending>) {
+    if path.is_dir() {
+        return;
+    }
+
+    pending.entry(path).or_insert(Pending {
+        size: 0,
+        changed: Instant::now(),
+    });
+}
+
+/// A folder copied or moved in may have files written before it was watched
+fn add_pending_folder(
+    folder: &Path,
+    scan_options: &ScanOptions,
+    pending: &mut HashMap<PathBuf, Pending>,
+) {
+    let mut files = Vec::new();
+    scanner::collect_directory(folder, scan_options, &mut files, &mut Vec::new());
+    for file in files {
+        add_pending(file, pending);
+    }
+}
+
+/// Files whose size hasn't changed for `SETTLE_TIME`
+fn settled_files(pending: &mut HashMap<PathBuf, Pending>) -> Vec<PathBuf> {
+    let mut settled = Vec::new();
+
+    pending.retain(|path, file| {
+        let Ok(metadata) = fs::metadata(path) else {
+            // deleted before it was finished
+            return false;
+        };
+
+        if metadata.len() != file.size {
+            file.size = metadata.len();
+            file.changed = Instant::now();
+            return true;
+        }
+        if file.changed.elapsed() < SETTLE_TIME {
+            return true;
+        }
+
+        settled.push(path.clone());
+        false
+    });
+
+    settled
+}
+
+fn finish_source(path: &Path, options: &WatchOptions, failed: bool) {
+    let folder = if failed {
+        &options.failed
+    } else {
+        &options.done
+    };
+
+    // the preset may have moved it already
+    if let Some(folder) = folder
+        && path.exists()
+        && let Err(e) = source_handling::move_preserving_path(path, &options.inbox, folder)
+    {
+        eprintln!("Couldn't move {}: {e}", path.display());
+    }
+}
+
+/// Adds files that have been written to `written`, files still being written to `pending`
+fn handle_event(
+    event: notify::Event,
+    scan_options: &ScanOptions,
+    pending: &mut HashMap<PathBuf, Pending>,
+    written: &mut Vec<PathBuf>,
+) {
+    match event.kind {
+        // inotify reports when the writer is done with the file
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+            for path in event.paths {
+                pending.remove(&path);
+                written.push(path);
+            }
+        }
+        // moved in, only the last path is the new location
+        EventKind::Modify(ModifyKind::Name(RenameMode::To | RenameMode::Both)) => {
+            if let Some(path) = event.paths.last() {
+                if path.is_dir() {
+                    add_pending_folder(path, scan_options, pending);
+                } else {
+                    pending.remove(path);
+                    written.push(path.clone());
+                }
+            }
+        }
+        EventKind::Create(CreateKind::Folder) => {
+            for path in event.paths {
+                add_pending_folder(&path, scan_options, pending);
+            }
+        }
+        EventKind::Create(_) | EventKind::Modify(_) => {
+            for path in event.paths {
+                add_pending(path, pending);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Converts every audio file written into the inbox with the preset, until the process is
+/// stopped. Files already in the inbox are converted first
+pub fn run(mut options: WatchOptions) -> Result<(), String> {
+    // events report absolute paths, sources keep their path relative to the inbox when moved
+    options.inbox = fs::canonicalize(&options.inbox)
+        .map_err(|e| format!("Couldn't watch {}: {e}", options.inbox.display()))?;
+
+    let mut settings = AudioConverterApp::stored_settings();
+    let preset = Preset::find(&options.preset, &settings)
+        .ok_or(format!("No preset named \"{}\"", options.preset))?;
+    preset.apply(&mut settings);
+    settings.out_multiple_targets = false;
+    // moving to the done folder replaces what the preset does with sources
+    if options.done.is_some() {
+        settings.out_source_action = SourceAction::Keep;
+    }
+    if let Some(output) = &options.output {
+        settings.out_directory = output.clone();
+    }
+
+    let scan_options = ScanOptions {
+        recursive: true,
+        ..ScanOptions::from_settings(&settings)
+    };
+    let written_folders = options.written_folders(&settings);
+
+    let (tx, rx) = mpsc::channel::<notify::Result<notify::Event>>();
+    let mut watcher = notify::recommended_watcher(tx).map_err(|e| e.to_string())?;
+    watcher
+        .watch(&options.inbox, RecursiveMode::Recursive)
+        .map_err(|e| format!("Couldn't watch {}: {e}", options.inbox.display()))?;
+
+    let mut pending: HashMap<PathBuf, Pending> = HashMap::new();
+    add_pending_folder(&options.inbox, &scan_options, &mut pending);
+
+    let mut tasks_manager = TasksManager::new(Arc::new(CoverArtCache::new()));
+    let mut converting: HashSet<PathBuf> = HashSet::new();
+
+    println!(
+        "Watching {} with \"{}\", converting into {}",
+        options.inbox.display(),
+        preset.name,
+        settings.out_directory
+    );
+
+    loop {
+        let mut written = Vec::new();
+
+        let mut events = Vec::new();
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => events.push(event),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err("The watch stopped unexpectedly".to_string());
+            }
+        }
+        events.extend(rx.try_iter());
+
+        for event in events {
+            match event {
+                Ok(event) => handle_event(event, &scan_options, &mut pending, &mut written),
+                Err(e) => eprintln!("Watch error: {e}"),
+            }
+        }
+
+        written.extend(settled_files(&mut pending));
+
+        for path in written {
+            if converting.contains(&path) || is_ignored(&path, &written_folders) || !path.is_file()
+            {
+                continue;
+            }
+
+            let kind = scan_options.classify(&path);
+            if kind == InputKind::Rejected {
+                continue;
+            }
+
+            match AudioFile::new(path.clone()) {
+                Ok(file) => {
+                    println!("Queued {}", path.display());
+                    converting.insert(path);
+                    tasks_manager.queue_audio_file(file, &settings);
+                }
+                Err(e) if e.is_ignorable() || kind == InputKind::Unknown => {}
+                Err(e) => {
+                    eprintln!("Failed {}: {e}", path.display());
+                    finish_source(&path, &options, true);
+                }
+            }
+        }
+
+        tasks_manager.update(&settings);
+
+        for result in tasks_manager.results.drain(..) {
+            match &result.error {
+                None if result.skipped => println!("Up to date {}", result.path.display()),
+                None if result.concealed_errors > 0 => println!(
+                    "Converted {}, skipping {} decoding error(s)",
+                    result.path.display(),
+                    result.concealed_errors
+                ),
+                None => println!("Converted {}", result.path.display()),
+                Some(e) => eprintln!("Failed {}: {e}", result.path.display()),
+            }
+            if let Some(e) = &result.source_error {
+                eprintln!("{}: {e}", result.path.display());
+            }
+
+            finish_source(&result.path, &options, result.error.is_some());
+            converting.remove(&result.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_every_option() {
+        let options = WatchOptions::parse(&args(&[
+            "inbox",
+            "--preset",
+            "Car MP3 320",
+            "--output",
+            "out",
+            "--done",
+            "inbox/done",
+            "--failed",
+            "failed",
+        ]))
+        .unwrap();
+
+        assert_eq!(options.inbox, PathBuf::from("inbox"));
+        assert_eq!(options.preset, "Car MP3 320");
+        assert_eq!(options.output.as_deref(), Some("out"));
+        assert_eq!(options.done, Some(PathBuf::from("inbox/done")));
+        assert_eq!(options.failed, Some(PathBuf::from("failed")));
+    }
+
+    #[test]
+    fn only_the_preset_is_required() {
+        let options = WatchOptions::parse(&args(&["inbox", "--preset", "Archive FLAC"])).unwrap();
+
+        assert_eq!(options.output, None);
+        assert_eq!(options.done, None);
+        assert_eq!(options.failed, None);
+    }
+
+    #[test]
+    fn files_written_by_the_watch_are_ignored() {
+        let temp = TempDir::new("watch-written");
+        let inbox = temp.path().join("inbox");
+        let options = WatchOptions {
+            done: Some(inbox.join("done")),
+            ..WatchOptions::parse(&args(&[inbox.to_str().unwrap(), "--preset", "p"])).unwrap()
+        };
+        let settings = Settings {
+            out_directory: inbox.join("out").to_string_lossy().to_string(),
+            out_source_action: SourceAction::MoveToFolder,
+            out_source_folder: inbox.join("moved").to_string_lossy().to_string(),
+            ..Settings::default()
+        };
+        let written_folders = options.written_folders(&settings);
+
+        for folder in ["done", "out", "moved"] {
+            assert!(is_ignored(
+                &inbox.join(folder).join("a.flac"),
+                &written_folders
+            ));
+        }
+        assert!(!is_ignored(&inbox.join("a.flac"), &written_folders));
+    }
+
+    #[test]
+    fn rejects_incomplete_arguments() {
+        assert!(WatchOptions::parse(&[]).is_err());
+        assert!(WatchOptions::parse(&args(&["inbox"])).is_err());
+        assert!(WatchOptions::parse(&args(&["inbox", "--preset"])).is_err());
+        assert!(WatchOptions::parse(&args(&["inbox", "--preset", "p", "--verbose", "1"])).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+/// Works through a queue on a thread per core, sending each result back as soon as it's ready so
+/// the UI can show it while the rest is still being worked on
+pub struct WorkerPool<R> {
+    rx: mpsc::Receiver<R>,
+    total: Arc<AtomicUsize>,
+    done: Arc<AtomicUsize>,
+    cancelled: Arc<AtomicBool>,
+    finished: bool,
+}
+
+impl<R: Send + 'static> WorkerPool<R> {
+    /// Runs `prepare` on another thread to list the items, it can send results of its own, then
+    /// `work` on every item. Items `work` returns None for are only counted as done
+    pub fn start<T, P, F>(prepare: P, work: F) -> Self
+    where
+        T: Send + 'static,
+        P: FnOnce(&mpsc::Sender<R>) -> Vec<T> + Send + 'static,
+        F: Fn(T) -> Option<R> + Send + Sync + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let total = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicUsize::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let pool = Self {
+            rx,
+            total: total.clone(),
+            done: done.clone(),
+            cancelled: cancelled.clone(),
+            finished: false,
+        };
+
+        thread::spawn(move || {
+            let items = prepare(&tx);
+            total.store(items.len(), Ordering::Relaxed);
+
+            let queue = Arc::new(Mutex::new(VecDeque::from(items)));
+            let work = Arc::new(work);
+            let worker_count = thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4);
+
+            for _ in 0..worker_count {
+                let queue = queue.clone();
+                let tx = tx.clone();
+                let done = done.clone();
+                let cancelled = cancelled.clone();
+                let work = work.clone();
+
+                thread::spawn(move || {
+                    while !cancelled.load(Ordering::Relaxed) {
+                        let Some(item) = queue.lock().unwrap().pop_front() else {
+                            break;
+                        };
+
+                        if let Some(result) = work(item)
+                            && tx.send(result).is_err()
+                        {
+                            break;
+                        }
+                        done.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+
+        pool
+    }
+
+    /// Returns the results sent since the last call, called every frame
+    pub fn receive(&mut self) -> Vec<R> {
+        let mut results = Vec::new();
+
+        loop {
+            match self.rx.try_recv() {
+                Ok(result) => results.push(result),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+
+        results
+    }
+
+    /// Workers stop after the item they're on, the rest of the queue is dropped
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Zero until the items have been listed
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_item_is_worked_on() {
+        let mut pool = WorkerPool::start(
+            |tx| {
+                tx.send(-1).unwrap();
+                (0..100).collect()
+            },
+            |i: i32| (i % 2 == 0).then_some(i * 2),
+        );
+
+        let mut results = Vec::new();
+        while !pool.is_finished() {
+            results.extend(pool.receive());
+            thread::yield_now();
+        }
+        results.sort();
+
+        let expected: Vec<i32> = std::iter::once(-1)
+            .chain((0..100).filter(|i| i % 2 == 0).map(|i| i * 2))
+            .collect();
+        assert_eq!(results, expected);
+        assert_eq!(pool.total(), 100);
+        assert_eq!(pool.done(), 100);
+    }
+}